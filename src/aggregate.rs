@@ -0,0 +1,350 @@
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+};
+
+use clap::{Args, ValueEnum};
+use gnuplot::{AutoOption, AxesCommon, Coordinate, Figure, LabelOption, PlotOption, Tick};
+use serde::Serialize;
+
+use crate::{
+	collect::{collect_values_per_language, ParamValueMap},
+	dataset::{Dataset, Grouping},
+	graph::{COLORS, PLOT_DIR},
+	nexus::parameter_order,
+	util::ensure_dir,
+};
+
+const FAMILIES_DIR: &str = "out/families";
+
+/// Summarize each parameter's values per family or genus: majority value, entropy and size
+#[derive(Debug, Args)]
+pub struct Aggregate {
+	#[arg(long, value_enum, default_value_t = Grouping::Family)]
+	by: Grouping,
+
+	/// Only these parameters, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	/// Leave out groups with fewer coded languages for a parameter
+	#[arg(long, default_value_t = 1)]
+	min_languages: usize,
+}
+
+/// Write a family's typological profile, the share of each value of every parameter,
+/// as csv and as a stacked bar chart
+#[derive(Debug, Args)]
+pub struct FamilyProfile {
+	family: String,
+
+	/// Only these parameters, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupSummary {
+	group: String,
+	parameter_id: String,
+	languages: usize,
+	values: usize,
+	majority: String,
+	majority_name: String,
+	majority_share: f32,
+	// in bits; 0 when the whole group agrees
+	entropy: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileRow {
+	parameter_id: String,
+	parameter_name: String,
+	value: String,
+	value_name: String,
+	languages: usize,
+	genera: usize,
+	share: f32,
+}
+
+impl Aggregate {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let param_ids = parameters(dataset, &self.params)?;
+		let values = collect_values_per_language(dataset);
+
+		let rows = dataset
+			.groups(self.by)
+			.into_iter()
+			.flat_map(|group| {
+				let lang_ids = dataset.languages_of_group(self.by, group);
+				param_ids
+					.iter()
+					.filter_map(|p| summarize(dataset, &values, group, lang_ids, p))
+					.filter(|s| s.languages >= self.min_languages)
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+
+		let by = self.by.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+		let path = out_dir.join(format!("out/aggregate-{by}.csv"));
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in rows.iter() {
+			w.serialize(row)
+				.map_err(|e| format!("{e} @{}/{}", path.display(), row.group))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!("{} {by} by parameter summaries written to {}", rows.len(), path.display());
+
+		Ok(())
+	}
+}
+
+impl FamilyProfile {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let lang_ids = dataset.languages_of_family(&self.family);
+		if lang_ids.is_empty() {
+			return Err(format!("family {} not found", self.family));
+		}
+
+		let param_ids = parameters(dataset, &self.params)?;
+		let rows = profile(dataset, lang_ids, &param_ids);
+		if rows.is_empty() {
+			return Err(format!("no values coded for family {}", self.family));
+		}
+
+		let name = file_name(&self.family);
+		let path = out_dir.join(FAMILIES_DIR).join(format!("{name}.csv"));
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in rows.iter() {
+			w.serialize(row)
+				.map_err(|e| format!("{e} @{}/{}", path.display(), row.parameter_id))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!(
+			"{} languages, {} parameters coded, written to {}",
+			lang_ids.len(),
+			rows.iter().map(|r| &r.parameter_id).collect::<HashSet<_>>().len(),
+			path.display()
+		);
+
+		plot_profile(&self.family, &rows, out_dir, &format!("family-{name}"))
+	}
+}
+
+fn parameters(dataset: &Dataset, params: &[String]) -> Result<Vec<String>, String> {
+	if let Some(p) = params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+		return Err(format!("parameter {p} not found"));
+	}
+
+	let mut param_ids = match params.is_empty() {
+		true => dataset.parameters.keys().cloned().collect::<Vec<_>>(),
+		false => params.to_vec(),
+	};
+	param_ids.sort_by(|a, b| parameter_order(a).cmp(&parameter_order(b)).then(a.cmp(b)));
+	Ok(param_ids)
+}
+
+// None when no language of the group is coded for the parameter
+pub fn summarize(
+	dataset: &Dataset,
+	values: &HashMap<String, ParamValueMap>,
+	group: &str,
+	lang_ids: &[String],
+	param_id: &str,
+) -> Option<GroupSummary> {
+	let mut counts: HashMap<&String, usize> = HashMap::new();
+	for v in lang_ids.iter().filter_map(|id| values.get(id)?.get(param_id)) {
+		*counts.entry(v).or_default() += 1;
+	}
+	let n = counts.values().sum::<usize>();
+	if n == 0 {
+		return None;
+	}
+
+	// ties go to the value first in code order
+	let order = dataset.parameter_values(param_id);
+	let (majority, count) = counts
+		.iter()
+		.max_by(|a, b| {
+			a.1.cmp(b.1).then_with(|| {
+				let position = |v: &String| order.iter().position(|o| o == v).unwrap_or(usize::MAX);
+				position(b.0).cmp(&position(a.0))
+			})
+		})
+		.map(|(v, c)| ((*v).clone(), *c))?;
+
+	let entropy = counts
+		.values()
+		.map(|c| *c as f32 / n as f32)
+		.map(|p| -p * p.log2())
+		.sum::<f32>();
+
+	Some(GroupSummary {
+		group: group.to_string(),
+		parameter_id: param_id.to_string(),
+		languages: n,
+		values: counts.len(),
+		majority_name: dataset.codes.name(param_id, &majority).unwrap_or(&majority).to_string(),
+		majority,
+		majority_share: count as f32 / n as f32,
+		entropy: entropy.max(0.0),
+	})
+}
+
+// share of each value among the coded languages, parameters in the given order
+// and values in code order
+pub fn profile(dataset: &Dataset, lang_ids: &[String], param_ids: &[String]) -> Vec<ProfileRow> {
+	let values = collect_values_per_language(dataset);
+
+	let mut rows = vec![];
+	for param_id in param_ids {
+		let coded = lang_ids
+			.iter()
+			.filter_map(|id| Some((id, values.get(id)?.get(param_id)?)))
+			.collect::<Vec<_>>();
+		if coded.is_empty() {
+			continue;
+		}
+
+		for value in dataset.parameter_values(param_id) {
+			let langs = coded.iter().filter(|(_, v)| **v == value).map(|(id, _)| *id).collect::<Vec<_>>();
+			if langs.is_empty() {
+				continue;
+			}
+
+			let genera = langs
+				.iter()
+				.map(|id| dataset.language(id).and_then(|l| l.genus.as_ref()).unwrap_or(id))
+				.collect::<HashSet<_>>()
+				.len();
+
+			rows.push(ProfileRow {
+				parameter_id: param_id.clone(),
+				parameter_name: dataset.parameters.get(param_id).map(|p| p.name.clone()).unwrap_or_default(),
+				value_name: dataset.codes.name(param_id, &value).unwrap_or(&value).to_string(),
+				value,
+				languages: langs.len(),
+				genera,
+				share: langs.len() as f32 / coded.len() as f32,
+			});
+		}
+	}
+
+	rows
+}
+
+// one bar per parameter, its values stacked from the bottom in code order; boxes
+// reaching the running total are drawn tallest first so that each shows its own share
+fn plot_profile(family: &str, rows: &[ProfileRow], out_dir: &Path, filename: &str) -> Result<(), String> {
+	let plot_dir = ensure_dir(out_dir.join(PLOT_DIR))?;
+
+	let mut param_ids: Vec<&String> = vec![];
+	let mut stacks: Vec<Vec<(f64, &ProfileRow)>> = vec![];
+	for row in rows {
+		if param_ids.last() != Some(&&row.parameter_id) {
+			param_ids.push(&row.parameter_id);
+			stacks.push(vec![]);
+		}
+		if let Some(stack) = stacks.last_mut() {
+			let top = stack.last().map(|(t, _)| *t).unwrap_or(0.0) + row.share as f64;
+			stack.push((top, row));
+		}
+	}
+
+	let mut figure = Figure::new();
+	figure.set_title(family);
+	let ax = figure.axes2d();
+	ax.set_y_range(AutoOption::Fix(0.0), AutoOption::Fix(1.0));
+	ax.set_x_range(AutoOption::Fix(-0.5), AutoOption::Fix(param_ids.len() as f64 - 0.5));
+	ax.set_y_label("share of coded languages", &[]);
+	ax.set_x_ticks_custom(
+		param_ids
+			.iter()
+			.enumerate()
+			.map(|(x, id)| Tick::Major(x as f64, AutoOption::Fix(id.to_string()))),
+		&[],
+		&[LabelOption::Rotate(90.0), LabelOption::Font("", 8.0)],
+	);
+
+	let depth = stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+	for level in (0..depth).rev() {
+		let (xs, ys): (Vec<_>, Vec<_>) = stacks
+			.iter()
+			.enumerate()
+			.filter_map(|(x, s)| s.get(level).map(|(top, _)| (x as f64, *top)))
+			.unzip();
+		let ws = vec![0.8; xs.len()];
+		ax.boxes_set_width(
+			xs,
+			ys,
+			ws,
+			&[
+				PlotOption::Color(COLORS[level % COLORS.len()]),
+				PlotOption::FillAlpha(1.0),
+				PlotOption::BorderColor("white"),
+			],
+		);
+	}
+
+	for (x, stack) in stacks.iter().enumerate() {
+		for (top, row) in stack.iter().filter(|(_, r)| r.share >= 0.15) {
+			ax.label(
+				&row.value_name,
+				Coordinate::Axis(x as f64),
+				Coordinate::Axis(top - row.share as f64 / 2.0),
+				&[LabelOption::Rotate(90.0), LabelOption::Font("", 7.0)],
+			);
+		}
+	}
+
+	let (width_px, height_px) = (200 + 24 * param_ids.len() as u32, 600);
+
+	figure
+		.save_to_svg(plot_dir.join(format!("{}.svg", filename)), width_px, height_px)
+		.map_err(|e| format!("{e} @save_to_svg"))?;
+
+	figure
+		.save_to_png(plot_dir.join(format!("{}.png", filename)), width_px, height_px)
+		.map_err(|e| format!("{e} @save_to_png"))?;
+
+	Ok(())
+}
+
+// family names have spaces and slashes, e.g. "Trans-New Guinea"
+fn file_name(s: &str) -> String {
+	s.chars()
+		.map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+		.collect()
+}
+
+#[test]
+fn aggregate_toy() {
+	let dataset = crate::dataset::toy();
+	let values = collect_values_per_language(&dataset);
+
+	let s = summarize(&dataset, &values, "F1", dataset.languages_of_family("F1"), "P2").unwrap();
+	assert_eq!((s.languages, s.values), (3, 2));
+	assert_eq!(s.majority, "1");
+	assert!((s.entropy - 0.9183).abs() < 1e-3);
+	assert!(summarize(&dataset, &values, "F3", dataset.languages_of_family("F3"), "P2").is_none());
+
+	let param_ids = ["P1".to_string(), "P2".to_string()];
+	let rows = profile(&dataset, dataset.languages_of_family("F1"), &param_ids);
+	let shares = rows
+		.iter()
+		.filter(|r| r.parameter_id == "P1")
+		.map(|r| r.share)
+		.sum::<f32>();
+	assert!((shares - 1.0).abs() < 1e-6);
+	assert_eq!(file_name("Trans-New Guinea"), "Trans-New_Guinea");
+}
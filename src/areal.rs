@@ -0,0 +1,413 @@
+use std::{collections::HashMap, path::Path};
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+	collect::collect_values_per_param,
+	dataset::Dataset,
+	geo::{coordinates, great_circle_km},
+	util::{ensure_dir, Rng},
+};
+
+const AUTOCORRELATION_FILE: &str = "out/areal.csv";
+const VALUES_FILE: &str = "out/areal-values.csv";
+const HOTSPOTS_FILE: &str = "out/areal-hotspots.csv";
+
+/// Measure how strongly each parameter's values cluster in space (join counts and Moran's I
+/// over the k nearest neighbours) and list the areas where a value is over-represented
+#[derive(Debug, Args)]
+pub struct Areal {
+	/// Only these parameters, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	/// Nearest coded languages each language is joined to
+	#[arg(long, default_value_t = 5)]
+	k: usize,
+
+	/// Random relabellings the join count is compared against
+	#[arg(long, default_value_t = 199)]
+	permutations: usize,
+
+	#[arg(long, default_value_t = 1)]
+	seed: u64,
+
+	/// Radius of the neighbourhoods searched for hotspots
+	#[arg(long, default_value_t = 1000.0)]
+	radius_km: f64,
+
+	/// Coded languages a neighbourhood needs to count as a hotspot
+	#[arg(long, default_value_t = 5)]
+	min_languages: usize,
+
+	/// z score of the value's local count a hotspot has to reach
+	#[arg(long, default_value_t = 3.0)]
+	min_z: f64,
+
+	/// Number of parameters printed after writing the tables
+	#[arg(long, default_value_t = 10)]
+	top: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Autocorrelation {
+	parameter_id: String,
+	parameter_name: String,
+	languages: usize,
+	values: usize,
+	// share of the k-nearest-neighbour joins linking languages of the same value
+	same_share: f64,
+	expected_share: f64,
+	z: f64,
+	p: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueAutocorrelation {
+	parameter_id: String,
+	value: String,
+	value_name: String,
+	languages: usize,
+	morans_i: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hotspot {
+	parameter_id: String,
+	value: String,
+	value_name: String,
+	center_id: String,
+	center_name: String,
+	latitude: f64,
+	longitude: f64,
+	languages: usize,
+	with_value: usize,
+	local_share: f64,
+	global_share: f64,
+	z: f64,
+}
+
+// the languages with coordinates, each with all the others by increasing distance
+pub struct Located {
+	ids: Vec<String>,
+	coords: Vec<(f64, f64)>,
+	nearest: Vec<Vec<u32>>,
+}
+
+impl Located {
+	pub fn new(dataset: &Dataset, lang_ids: &[&String]) -> Self {
+		let (ids, coords): (Vec<_>, Vec<_>) = lang_ids
+			.iter()
+			.filter_map(|id| Some(((*id).clone(), coordinates(dataset.language(id)?)?)))
+			.unzip();
+
+		let nearest = (0..ids.len())
+			.map(|i| {
+				let mut others = (0..ids.len())
+					.filter(|j| *j != i)
+					.map(|j| (great_circle_km(coords[i], coords[j]), j as u32))
+					.collect::<Vec<_>>();
+				others.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+				others.into_iter().map(|(_, j)| j).collect()
+			})
+			.collect();
+
+		Self { ids, coords, nearest }
+	}
+
+	pub fn index(&self) -> HashMap<&str, usize> {
+		self.ids.iter().enumerate().map(|(i, id)| (id.as_str(), i)).collect()
+	}
+
+	fn knn(&self, i: usize, k: usize, coded: &[Option<usize>]) -> Vec<usize> {
+		self.nearest[i]
+			.iter()
+			.map(|j| *j as usize)
+			.filter(|j| coded[*j].is_some())
+			.take(k)
+			.collect()
+	}
+
+	// i itself and the coded languages within km of it
+	fn within(&self, i: usize, km: f64, coded: &[Option<usize>]) -> Vec<usize> {
+		std::iter::once(i)
+			.chain(
+				self.nearest[i]
+					.iter()
+					.map(|j| *j as usize)
+					.take_while(|j| great_circle_km(self.coords[i], self.coords[*j]) <= km),
+			)
+			.filter(|j| coded[*j].is_some())
+			.collect()
+	}
+}
+
+impl Areal {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let per_param = collect_values_per_param(dataset);
+		let mut param_ids = match self.params.is_empty() {
+			true => per_param.keys().cloned().collect::<Vec<_>>(),
+			false => self.params.clone(),
+		};
+		param_ids.sort();
+
+		let mut lang_ids = param_ids
+			.iter()
+			.filter_map(|p| per_param.get(p))
+			.flat_map(|vl| vl.values().flatten())
+			.collect::<Vec<_>>();
+		lang_ids.sort();
+		lang_ids.dedup();
+		let located = Located::new(dataset, &lang_ids);
+		let index = located.index();
+
+		let (mut features, mut values, mut hotspots) = (vec![], vec![], vec![]);
+		for param_id in param_ids.iter() {
+			let Some(value_langs) = per_param.get(param_id) else {
+				continue;
+			};
+			let mut vs = value_langs.keys().collect::<Vec<_>>();
+			vs.sort();
+
+			// value index of each located language, None when not coded
+			let mut labels = vec![None; located.ids.len()];
+			for (v, value) in vs.iter().enumerate() {
+				for lang_id in value_langs[*value].iter() {
+					if let Some(i) = index.get(lang_id.as_str()) {
+						labels[*i] = Some(v);
+					}
+				}
+			}
+
+			let Some(feature) = self.autocorrelation(dataset, param_id, &located, &labels, vs.len()) else {
+				continue;
+			};
+			features.push(feature);
+
+			let joins = joins(&located, &labels, self.k);
+			for (v, value) in vs.iter().enumerate() {
+				let value_name = dataset.codes.name(param_id, value).unwrap_or(value).to_string();
+				let indicator = labels.iter().map(|l| l.map(|l| (l == v) as u8 as f64)).collect::<Vec<_>>();
+				if let Some(morans_i) = morans_i(&joins, &indicator) {
+					values.push(ValueAutocorrelation {
+						parameter_id: param_id.clone(),
+						value: value.to_string(),
+						value_name: value_name.clone(),
+						languages: labels.iter().filter(|l| **l == Some(v)).count(),
+						morans_i,
+					});
+				}
+
+				hotspots.extend(self.hotspots(dataset, &located, &labels, v).into_iter().map(|mut h| {
+					h.parameter_id = param_id.clone();
+					h.value = value.to_string();
+					h.value_name = value_name.clone();
+					h
+				}));
+			}
+		}
+
+		features.sort_by(|a, b| b.z.total_cmp(&a.z).then_with(|| a.parameter_id.cmp(&b.parameter_id)));
+
+		write_rows(out_dir, AUTOCORRELATION_FILE, &features)?;
+		write_rows(out_dir, VALUES_FILE, &values)?;
+		write_rows(out_dir, HOTSPOTS_FILE, &hotspots)?;
+
+		for f in features.iter().take(self.top) {
+			println!(
+				"{:>8} {:<50} {:>5} same {:.3} expected {:.3} z {:>6.2} p {:.3}",
+				f.parameter_id, f.parameter_name, f.languages, f.same_share, f.expected_share, f.z, f.p
+			);
+		}
+		println!(
+			"{} parameters written to {}, {} hotspots to {}",
+			features.len(),
+			out_dir.join(AUTOCORRELATION_FILE).display(),
+			hotspots.len(),
+			out_dir.join(HOTSPOTS_FILE).display()
+		);
+
+		Ok(())
+	}
+
+	fn autocorrelation(
+		&self,
+		dataset: &Dataset,
+		param_id: &str,
+		located: &Located,
+		labels: &[Option<usize>],
+		value_count: usize,
+	) -> Option<Autocorrelation> {
+		let n = labels.iter().filter(|l| l.is_some()).count();
+		if n < 2 {
+			return None;
+		}
+
+		let joins = joins(located, labels, self.k);
+		let observed = same_share(&joins, labels)?;
+
+		let mut counts = vec![0usize; value_count];
+		labels.iter().flatten().for_each(|v| counts[*v] += 1);
+		let expected = counts.iter().map(|c| (c * c.saturating_sub(1)) as f64).sum::<f64>() / (n * (n - 1)) as f64;
+
+		// the same joins with the values dealt out again at random
+		let mut rng = Rng::new(self.seed);
+		let coded = (0..labels.len()).filter(|i| labels[*i].is_some()).collect::<Vec<_>>();
+		let mut shuffled = labels.to_vec();
+		let mut deal = coded.iter().map(|i| labels[*i]).collect::<Vec<_>>();
+		let mut shares = vec![];
+		for _ in 0..self.permutations {
+			rng.shuffle(&mut deal);
+			for (i, l) in coded.iter().zip(deal.iter()) {
+				shuffled[*i] = *l;
+			}
+			shares.extend(same_share(&joins, &shuffled));
+		}
+
+		let mean = shares.iter().sum::<f64>() / shares.len().max(1) as f64;
+		let sd = (shares.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / shares.len().max(1) as f64).sqrt();
+		let at_least = shares.iter().filter(|s| **s >= observed).count();
+
+		Some(Autocorrelation {
+			parameter_id: param_id.to_string(),
+			parameter_name: dataset.parameters.get(param_id).map(|p| p.name.clone()).unwrap_or_default(),
+			languages: n,
+			values: counts.iter().filter(|c| **c > 0).count(),
+			same_share: observed,
+			expected_share: expected,
+			z: if sd > 0.0 { (observed - mean) / sd } else { 0.0 },
+			p: (at_least + 1) as f64 / (shares.len() + 1) as f64,
+		})
+	}
+
+	// neighbourhoods where value v is more frequent than the binomial expectation, strongest
+	// first, leaving out those whose centre is within the radius of a stronger one
+	fn hotspots(&self, dataset: &Dataset, located: &Located, labels: &[Option<usize>], v: usize) -> Vec<Hotspot> {
+		let n = labels.iter().filter(|l| l.is_some()).count();
+		let global = labels.iter().filter(|l| **l == Some(v)).count() as f64 / n.max(1) as f64;
+		if global <= 0.0 || global >= 1.0 {
+			return vec![];
+		}
+
+		let mut candidates = (0..labels.len())
+			.filter(|i| labels[*i] == Some(v))
+			.filter_map(|i| {
+				let around = located.within(i, self.radius_km, labels);
+				let m = around.len();
+				if m < self.min_languages {
+					return None;
+				}
+
+				let with_value = around.iter().filter(|j| labels[**j] == Some(v)).count();
+				let z = (with_value as f64 - m as f64 * global) / (m as f64 * global * (1.0 - global)).sqrt();
+				(z >= self.min_z).then_some((z, i, m, with_value))
+			})
+			.collect::<Vec<_>>();
+		candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+		let mut picked: Vec<Hotspot> = vec![];
+		let mut centres: Vec<usize> = vec![];
+		for (z, i, m, with_value) in candidates {
+			if centres
+				.iter()
+				.any(|c| great_circle_km(located.coords[*c], located.coords[i]) <= self.radius_km)
+			{
+				continue;
+			}
+
+			centres.push(i);
+			picked.push(Hotspot {
+				parameter_id: String::new(),
+				value: String::new(),
+				value_name: String::new(),
+				center_id: located.ids[i].clone(),
+				center_name: dataset.language(&located.ids[i]).map(|l| l.name.clone()).unwrap_or_default(),
+				latitude: located.coords[i].0,
+				longitude: located.coords[i].1,
+				languages: m,
+				with_value,
+				local_share: with_value as f64 / m as f64,
+				global_share: global,
+				z,
+			});
+		}
+
+		picked
+	}
+}
+
+// (i, j) for each coded language i and each of its k nearest coded languages j
+fn joins(located: &Located, labels: &[Option<usize>], k: usize) -> Vec<(usize, usize)> {
+	(0..labels.len())
+		.filter(|i| labels[*i].is_some())
+		.flat_map(|i| located.knn(i, k, labels).into_iter().map(move |j| (i, j)))
+		.collect()
+}
+
+fn same_share(joins: &[(usize, usize)], labels: &[Option<usize>]) -> Option<f64> {
+	if joins.is_empty() {
+		return None;
+	}
+
+	let same = joins.iter().filter(|(i, j)| labels[*i] == labels[*j]).count();
+	Some(same as f64 / joins.len() as f64)
+}
+
+// Moran's I of a variable over the joins weighted 1; None where it has no variance
+fn morans_i(joins: &[(usize, usize)], x: &[Option<f64>]) -> Option<f64> {
+	let coded = x.iter().flatten().collect::<Vec<_>>();
+	let n = coded.len() as f64;
+	let mean = coded.iter().copied().sum::<f64>() / n;
+	let variance = coded.iter().map(|v| (*v - mean).powi(2)).sum::<f64>();
+	if joins.is_empty() || variance <= 0.0 {
+		return None;
+	}
+
+	let cross = joins
+		.iter()
+		.filter_map(|(i, j)| Some((x[*i]? - mean) * (x[*j]? - mean)))
+		.sum::<f64>();
+
+	Some(n / joins.len() as f64 * cross / variance)
+}
+
+fn write_rows<T: Serialize>(out_dir: &Path, file: &str, rows: &[T]) -> Result<(), String> {
+	let path = out_dir.join(file);
+	if let Some(dir) = path.parent() {
+		ensure_dir(dir)?;
+	}
+
+	let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+	for row in rows {
+		w.serialize(row).map_err(|e| format!("{e} @{}", path.display()))?;
+	}
+	w.flush().map_err(|e| format!("{e} @{}", path.display()))
+}
+
+#[test]
+fn areal_toy() {
+	let dataset = crate::dataset::toy();
+	let lang_ids = dataset.languages.keys().collect::<Vec<_>>();
+	let located = Located::new(&dataset, &lang_ids);
+	let index = located.index();
+
+	// Europe against Africa, dda left uncoded
+	let mut labels = vec![None; located.ids.len()];
+	for (id, v) in [("aaa", 0), ("aab", 0), ("bba", 0), ("cca", 1), ("ccb", 1)] {
+		if let Some(i) = index.get(id) {
+			labels[*i] = Some(v);
+		}
+	}
+
+	let joins = joins(&located, &labels, 1);
+	assert_eq!(joins.len(), 5);
+	assert_eq!(same_share(&joins, &labels), Some(1.0));
+
+	let indicator = labels.iter().map(|l| l.map(|l| (l == 0) as u8 as f64)).collect::<Vec<_>>();
+	assert_eq!(morans_i(&joins, &indicator), Some(1.0));
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use clap::ValueEnum;
+
+use crate::metadata::Metadata;
+
+const DEFAULT_SOURCES: &str = "sources.bib";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BibFormat {
+	Bibtex,
+	Text,
+}
+
+impl BibFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::Bibtex => "bib",
+			Self::Text => "txt",
+		}
+	}
+}
+
+// a reference as found in a Source column, e.g. "Dryer-2005[23-25]"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRef {
+	pub key: String,
+	pub pages: Option<String>,
+}
+
+impl From<&str> for SourceRef {
+	fn from(s: &str) -> Self {
+		let s = s.trim();
+		match s.split_once('[') {
+			Some((key, rest)) if rest.ends_with(']') => Self {
+				key: key.trim().to_string(),
+				pages: Some(rest.trim_end_matches(']').to_string()).filter(|p| !p.is_empty()),
+			},
+			_ => Self {
+				key: s.to_string(),
+				pages: None,
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+	pub entry_type: String,
+	pub key: String,
+	pub fields: Vec<(String, String)>,
+}
+
+impl Entry {
+	pub fn field(&self, name: &str) -> Option<&str> {
+		self
+			.fields
+			.iter()
+			.find(|(n, _)| n == name)
+			.map(|(_, v)| v.as_str())
+	}
+
+	pub fn to_bibtex(&self) -> String {
+		let fields = self
+			.fields
+			.iter()
+			.map(|(n, v)| format!("    {} = {{{}}}", n, v))
+			.intersperse(",\n".to_string())
+			.collect::<String>();
+
+		format!("@{}{{{},\n{}\n}}", self.entry_type, self.key, fields)
+	}
+
+	// roughly the Unified Style Sheet for Linguistics
+	pub fn to_text(&self) -> String {
+		let plain = |name: &str| self.field(name).map(|v| v.replace(['{', '}'], ""));
+		let sentence = |s: String| if s.ends_with('.') { s } else { s + "." };
+
+		let mut text = plain("author")
+			.or_else(|| plain("editor").map(|e| format!("{e} (ed.)")))
+			.unwrap_or_else(|| self.key.clone());
+		text = format!("{} {}.", sentence(text), plain("year").unwrap_or_else(|| "n.d.".to_string()));
+
+		if let Some(title) = plain("title") {
+			text = format!("{text} {title}.");
+		}
+
+		if let Some(container) = plain("journal").or_else(|| plain("booktitle")) {
+			let prefix = if self.field("booktitle").is_some() { "In " } else { "" };
+			text = format!("{text} {prefix}{container}");
+			if let Some(volume) = plain("volume") {
+				text = format!("{text} {volume}");
+			}
+			if let Some(pages) = plain("pages") {
+				text = format!("{text}. {pages}");
+			}
+			text += ".";
+		}
+
+		match (plain("address"), plain("publisher").or_else(|| plain("school"))) {
+			(Some(address), Some(publisher)) => text = format!("{text} {address}: {publisher}."),
+			(None, Some(publisher)) => text = format!("{text} {publisher}."),
+			_ => {}
+		}
+
+		text
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bibliography {
+	entries: HashMap<String, Entry>,
+	path: PathBuf, // where the entries were read from, for the not-found messages
+}
+
+impl Bibliography {
+	pub fn parse(text: &str) -> Self {
+		let entries = parse_entries(text)
+			.into_iter()
+			.map(|e| (e.key.clone(), e))
+			.collect();
+
+		Self {
+			entries,
+			path: PathBuf::from(DEFAULT_SOURCES),
+		}
+	}
+
+	// the file named by the metadata's dc:source, if it exists
+	pub fn read(metadata: &Metadata) -> Result<Self, String> {
+		let path = metadata
+			.dir
+			.join(metadata.source.as_deref().unwrap_or(DEFAULT_SOURCES));
+
+		if !path.exists() {
+			return Ok(Self {
+				path,
+				..Self::default()
+			});
+		}
+
+		let text = fs::read_to_string(&path).map_err(|e| format!("{e} @read_to_string/Bibliography::read"))?;
+		Ok(Self {
+			path,
+			..Self::parse(&text)
+		})
+	}
+
+	pub fn get(&self, key: &str) -> Option<&Entry> {
+		self.entries.get(key)
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	// one entry per key, in key order, with the pages cited for it
+	pub fn format(&self, refs: &[SourceRef], format: BibFormat) -> String {
+		let mut pages: HashMap<&str, Vec<&str>> = HashMap::new();
+		for r in refs {
+			let ps = pages.entry(r.key.as_str()).or_default();
+			if let Some(p) = r.pages.as_deref()
+				&& !ps.contains(&p)
+			{
+				ps.push(p);
+			}
+		}
+
+		let mut keys = pages.keys().copied().collect::<Vec<_>>();
+		keys.sort();
+		let source = self.path.display();
+
+		keys.into_iter()
+			.map(|key| {
+				let cited = pages[key].join(", ");
+				match (self.get(key), format) {
+					(Some(entry), BibFormat::Bibtex) if cited.is_empty() => entry.to_bibtex(),
+					(Some(entry), BibFormat::Bibtex) => format!("% cited: {cited}\n{}", entry.to_bibtex()),
+					(Some(entry), BibFormat::Text) if cited.is_empty() => entry.to_text(),
+					(Some(entry), BibFormat::Text) => format!("{} [{cited}]", entry.to_text()),
+					(None, BibFormat::Bibtex) => format!("% {key} not found in {source}"),
+					(None, BibFormat::Text) => format!("{key} (not found in {source})"),
+				}
+			})
+			.intersperse(match format {
+				BibFormat::Bibtex => "\n\n".to_string(),
+				BibFormat::Text => "\n".to_string(),
+			})
+			.collect::<String>()
+			+ "\n"
+	}
+}
+
+fn parse_entries(text: &str) -> Vec<Entry> {
+	let bytes = text.as_bytes();
+	let mut entries = vec![];
+	let mut i = 0;
+
+	while let Some(at) = text[i..].find('@') {
+		i += at + 1;
+		let Some(open) = text[i..].find(['{', '(']) else {
+			break;
+		};
+		let entry_type = text[i..i + open].trim().to_lowercase();
+		let close = if bytes[i + open] == b'{' { b'}' } else { b')' };
+		i += open + 1;
+
+		let Some(end) = find_close(bytes, i, close) else {
+			break;
+		};
+		let body = &text[i..end];
+		i = end + 1;
+
+		if matches!(entry_type.as_str(), "comment" | "preamble" | "string") {
+			continue;
+		}
+
+		if let Some(entry) = parse_body(entry_type, body) {
+			entries.push(entry);
+		}
+	}
+
+	entries
+}
+
+// position of the `close` byte ending a group which starts at `start`,
+// skipping over nested braces
+fn find_close(bytes: &[u8], start: usize, close: u8) -> Option<usize> {
+	let mut depth = 0usize;
+	for (i, b) in bytes.iter().enumerate().skip(start) {
+		match *b {
+			b'{' => depth += 1,
+			b'}' if depth > 0 => depth -= 1,
+			b if b == close && depth == 0 => return Some(i),
+			_ => {}
+		}
+	}
+
+	None
+}
+
+fn parse_body(entry_type: String, body: &str) -> Option<Entry> {
+	let (key, mut rest) = body.split_once(',')?;
+	let mut fields = vec![];
+
+	loop {
+		rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+		let Some((name, after)) = rest.split_once('=') else {
+			break;
+		};
+		let after = after.trim_start();
+		let bytes = after.as_bytes();
+
+		let (value, remaining) = match bytes.first() {
+			Some(b'{') => {
+				let end = find_close(bytes, 1, b'}')?;
+				(&after[1..end], &after[end + 1..])
+			}
+			Some(b'"') => {
+				let end = after[1..].find('"')? + 1;
+				(&after[1..end], &after[end + 1..])
+			}
+			_ => {
+				let end = after.find(',').unwrap_or(after.len());
+				(after[..end].trim(), &after[end..])
+			}
+		};
+
+		fields.push((
+			name.trim().to_lowercase(),
+			value.split_whitespace().collect::<Vec<_>>().join(" "),
+		));
+		rest = remaining;
+	}
+
+	Some(Entry {
+		entry_type,
+		key: key.trim().to_string(),
+		fields,
+	})
+}
+
+#[test]
+fn source_ref() {
+	assert_eq!(
+		SourceRef::from("Dryer-2005[23-25]"),
+		SourceRef {
+			key: "Dryer-2005".to_string(),
+			pages: Some("23-25".to_string())
+		}
+	);
+	assert_eq!(SourceRef::from(" Hinds-1986 ").pages, None);
+}
+
+#[test]
+fn parse_bib() {
+	let text = "@comment{ignored}
+@book{Abbott-2000,
+    author = {Abbott, Clifford},
+    address = {M{\\\"u}nchen},
+    publisher = \"Lincom Europa\",
+    title = {Oneida},
+    year = 2000
+}
+
+@article{Dryer-1992,
+    author = {Dryer, Matthew S.},
+    journal = {Language},
+    pages = {81--138},
+    title = {The {G}reenbergian word order correlations},
+    volume = {68},
+    year = {1992}
+}";
+
+	let bib = Bibliography::parse(text);
+	assert_eq!(bib.len(), 2);
+
+	let abbott = bib.get("Abbott-2000").unwrap();
+	assert_eq!(abbott.field("publisher"), Some("Lincom Europa"));
+	assert_eq!(abbott.field("year"), Some("2000"));
+
+	assert_eq!(
+		bib.get("Dryer-1992").unwrap().to_text(),
+		"Dryer, Matthew S. 1992. The Greenbergian word order correlations. Language 68. 81--138."
+	);
+
+	let refs = [
+		SourceRef::from("Dryer-1992[100]"),
+		SourceRef::from("Nobody-2000"),
+		SourceRef::from("Dryer-1992[102]"),
+	];
+	assert_eq!(
+		bib.format(&refs, BibFormat::Text),
+		"Dryer, Matthew S. 1992. The Greenbergian word order correlations. Language 68. 81--138. [100, 102]\n\
+		 Nobody-2000 (not found in sources.bib)\n"
+	);
+
+	let bibtex = bib.format(&refs[..1], BibFormat::Bibtex);
+	assert!(bibtex.starts_with("% cited: 100\n@article{Dryer-1992,\n"));
+	assert!(bibtex.contains("    journal = {Language},\n"));
+
+	let bib = Bibliography {
+		path: PathBuf::from("cldf/refs.bib"),
+		..bib
+	};
+	assert_eq!(
+		bib.format(&refs[1..2], BibFormat::Bibtex),
+		"% Nobody-2000 not found in cldf/refs.bib\n"
+	);
+}
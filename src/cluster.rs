@@ -0,0 +1,507 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use clap::{Args, ValueEnum};
+
+use crate::{
+	dataset::{Dataset, Grouping},
+	distance::{print_dropped, Comparer, DistanceMatrix, DistanceOptions},
+	graph::plot_dendrogram,
+	nexus::nexus_label,
+	util::ensure_dir,
+};
+
+const TREE_DIR: &str = "out/trees";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+	Upgma,
+	/// Neighbour-joining
+	Nj,
+}
+
+impl Method {
+	fn name(&self) -> &'static str {
+		match self {
+			Self::Upgma => "upgma",
+			Self::Nj => "nj",
+		}
+	}
+}
+
+/// Cluster languages by their typological distances into a Newick tree and dendrogram
+#[derive(Debug, Args)]
+pub struct Cluster {
+	/// Languages to cluster, all languages with values when omitted
+	lang_ids: Vec<String>,
+
+	#[arg(long, value_enum, default_value_t = Method::Upgma)]
+	method: Method,
+
+	#[command(flatten)]
+	options: DistanceOptions,
+
+	/// Report which families or genera do not come out as clades
+	#[arg(long, value_enum, default_value_t = Grouping::Family)]
+	compare: Grouping,
+
+	/// Leave out the languages with the most missing distances until none is missing,
+	/// which both methods require
+	#[arg(long)]
+	drop_incomplete: bool,
+}
+
+// a rooted tree whose branches carry the length leading to each child
+#[derive(Debug, Clone)]
+pub struct Tree {
+	pub label: Option<String>,
+	pub children: Vec<(Tree, f32)>,
+}
+
+impl Tree {
+	fn leaf(label: &str) -> Self {
+		Self {
+			label: Some(label.to_string()),
+			children: vec![],
+		}
+	}
+
+	fn join(children: Vec<(Tree, f32)>) -> Self {
+		Self {
+			label: None,
+			children,
+		}
+	}
+
+	pub fn leaves(&self) -> Vec<&str> {
+		match &self.label {
+			Some(label) if self.children.is_empty() => vec![label.as_str()],
+			_ => self.children.iter().flat_map(|(t, _)| t.leaves()).collect(),
+		}
+	}
+
+	// the leaves of every subtree, the whole tree included
+	pub fn clades(&self) -> Vec<Vec<&str>> {
+		let mut clades: Vec<Vec<&str>> = self.children.iter().flat_map(|(t, _)| t.clades()).collect();
+		clades.push(self.leaves());
+		clades
+	}
+
+	pub fn to_newick(&self) -> String {
+		format!("{};", self.newick_node())
+	}
+
+	fn newick_node(&self) -> String {
+		let label = self.label.as_deref().map(nexus_label).unwrap_or_default();
+		if self.children.is_empty() {
+			return label;
+		}
+
+		let children = self
+			.children
+			.iter()
+			.map(|(t, l)| format!("{}:{l:.6}", t.newick_node()))
+			.intersperse(",".to_string())
+			.collect::<String>();
+
+		format!("({children}){label}")
+	}
+}
+
+fn complete(matrix: &DistanceMatrix) -> Result<Vec<Vec<f32>>, String> {
+	match matrix.missing_pairs() {
+		0 => Ok(matrix
+			.distances
+			.iter()
+			.map(|row| row.iter().map(|d| d.unwrap_or_default()).collect())
+			.collect()),
+		n => Err(format!(
+			"{n} pairs share too few parameters to be clustered, pass --drop-incomplete or pick other languages"
+		)),
+	}
+}
+
+pub fn upgma(matrix: &DistanceMatrix) -> Result<Tree, String> {
+	let mut d = complete(matrix)?;
+	// (tree, number of leaves, height) of each cluster still to be joined
+	let mut clusters: Vec<Option<(Tree, usize, f32)>> =
+		matrix.lang_ids.iter().map(|id| Some((Tree::leaf(id), 1, 0.0))).collect();
+
+	for _ in 1..clusters.len() {
+		let active = (0..clusters.len()).filter(|i| clusters[*i].is_some()).collect::<Vec<_>>();
+		let Some((i, j)) = closest(&active, |i, j| d[i][j]) else {
+			break;
+		};
+
+		let (ti, si, hi) = clusters[i].take().unwrap_or_else(|| unreachable!());
+		let (tj, sj, hj) = clusters[j].take().unwrap_or_else(|| unreachable!());
+		let height = d[i][j] / 2.0;
+
+		for k in active.iter().copied().filter(|k| *k != i && *k != j) {
+			let dk = (d[i][k] * si as f32 + d[j][k] * sj as f32) / (si + sj) as f32;
+			d[i][k] = dk;
+			d[k][i] = dk;
+		}
+
+		clusters[i] = Some((Tree::join(vec![(ti, height - hi), (tj, height - hj)]), si + sj, height));
+	}
+
+	clusters
+		.into_iter()
+		.flatten()
+		.next()
+		.map(|(t, _, _)| t)
+		.ok_or_else(|| "no languages to cluster".to_string())
+}
+
+// the midpoint-rooted tree and the number of branches that came out negative, whose
+// length went to their sister branch instead
+pub fn neighbor_joining(matrix: &DistanceMatrix) -> Result<(Tree, usize), String> {
+	let mut d = complete(matrix)?;
+	let mut nodes: Vec<Option<Tree>> = matrix.lang_ids.iter().map(|id| Some(Tree::leaf(id))).collect();
+	let mut negative = 0;
+
+	loop {
+		let active = (0..nodes.len()).filter(|i| nodes[*i].is_some()).collect::<Vec<_>>();
+		let n = active.len();
+
+		if n <= 2 {
+			let mut rest = active.iter().filter_map(|i| nodes[*i].take());
+			return match (rest.next(), rest.next()) {
+				(Some(a), Some(b)) => {
+					let l = d[active[0]][active[1]];
+					if l < 0.0 {
+						negative += 1;
+					}
+					let half = l.max(0.0) / 2.0;
+					Ok((midpoint_root(Tree::join(vec![(a, half), (b, half)])), negative))
+				}
+				(Some(a), None) => Ok((a, negative)),
+				_ => Err("no languages to cluster".to_string()),
+			};
+		}
+
+		let r: HashMap<usize, f32> = active
+			.iter()
+			.map(|i| (*i, active.iter().map(|k| d[*i][*k]).sum()))
+			.collect();
+		let Some((i, j)) = closest(&active, |i, j| (n - 2) as f32 * d[i][j] - r[&i] - r[&j]) else {
+			break Err("no languages to cluster".to_string());
+		};
+
+		let li = d[i][j] / 2.0 + (r[&i] - r[&j]) / (2 * (n - 2)) as f32;
+		let (li, lj) = match (li, d[i][j] - li) {
+			(li, lj) if li < 0.0 => {
+				negative += 1;
+				(0.0, lj + li)
+			}
+			(li, lj) if lj < 0.0 => {
+				negative += 1;
+				(li + lj, 0.0)
+			}
+			ls => ls,
+		};
+
+		for k in active.iter().copied().filter(|k| *k != i && *k != j) {
+			let dk = (d[i][k] + d[j][k] - d[i][j]) / 2.0;
+			d[i][k] = dk;
+			d[k][i] = dk;
+		}
+
+		let ti = nodes[i].take().unwrap_or_else(|| unreachable!());
+		let tj = nodes[j].take().unwrap_or_else(|| unreachable!());
+		nodes[i] = Some(Tree::join(vec![(ti, li.max(0.0)), (tj, lj.max(0.0))]));
+	}
+}
+
+// an unrooted tree as adjacency lists of (neighbour, branch length), to root it elsewhere
+struct Unrooted {
+	labels: Vec<Option<String>>,
+	edges: Vec<Vec<(usize, f32)>>,
+}
+
+impl Unrooted {
+	fn new(tree: Tree) -> Self {
+		let mut u = Self {
+			labels: vec![],
+			edges: vec![],
+		};
+		u.add(tree);
+
+		// a root between two subtrees only splits the branch connecting them
+		if let [(a, la), (b, lb)] = u.edges[0][..] {
+			u.edges[0].clear();
+			for (x, y) in [(a, b), (b, a)] {
+				u.edges[x].retain(|(n, _)| *n != 0);
+				u.edges[x].push((y, la + lb));
+			}
+		}
+
+		u
+	}
+
+	fn add(&mut self, tree: Tree) -> usize {
+		let i = self.labels.len();
+		self.labels.push(tree.label);
+		self.edges.push(vec![]);
+		for (child, l) in tree.children {
+			let c = self.add(child);
+			self.edges[i].push((c, l));
+			self.edges[c].push((i, l));
+		}
+
+		i
+	}
+
+	// (distance, previous node) of every node on its path from start
+	fn paths(&self, start: usize) -> Vec<Option<(f32, usize)>> {
+		let mut paths = vec![None; self.labels.len()];
+		paths[start] = Some((0.0, start));
+		let mut stack = vec![(start, 0.0)];
+		while let Some((i, d)) = stack.pop() {
+			for (j, l) in self.edges[i].iter().copied() {
+				if paths[j].is_none() {
+					paths[j] = Some((d + l, i));
+					stack.push((j, d + l));
+				}
+			}
+		}
+
+		paths
+	}
+
+	fn farthest_leaf(&self, paths: &[Option<(f32, usize)>]) -> Option<(usize, f32)> {
+		(0..self.labels.len())
+			.filter(|i| self.labels[*i].is_some())
+			.filter_map(|i| Some((i, paths[i]?.0)))
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+	}
+
+	// node i with everything reachable from it but through the excluded neighbour
+	fn subtree(&self, i: usize, excluded: Option<usize>) -> Tree {
+		Tree {
+			label: self.labels[i].clone(),
+			children: self.edges[i]
+				.iter()
+				.filter(|(j, _)| Some(*j) != excluded)
+				.map(|(j, l)| (self.subtree(*j, Some(i)), *l))
+				.collect(),
+		}
+	}
+}
+
+// the tree rooted halfway along the longest path between two leaves
+pub fn midpoint_root(tree: Tree) -> Tree {
+	if tree.leaves().len() < 3 {
+		return tree;
+	}
+
+	let u = Unrooted::new(tree);
+	let Some(start) = u.labels.iter().position(|l| l.is_some()) else {
+		return u.subtree(0, None);
+	};
+	let Some((a, _)) = u.farthest_leaf(&u.paths(start)) else {
+		return u.subtree(start, None);
+	};
+	let from_a = u.paths(a);
+	let Some((b, length)) = u.farthest_leaf(&from_a) else {
+		return u.subtree(a, None);
+	};
+
+	// walk back from b until the branch holding the midpoint
+	let half = length / 2.0;
+	let mut i = b;
+	while let Some((d, prev)) = from_a[i]
+		&& i != a
+	{
+		let dp = from_a[prev].map(|(d, _)| d).unwrap_or_default();
+		if dp <= half {
+			return match half - dp {
+				l if l > 0.0 => Tree::join(vec![(u.subtree(prev, Some(i)), l), (u.subtree(i, Some(prev)), d - half)]),
+				_ => u.subtree(prev, None),
+			};
+		}
+		i = prev;
+	}
+
+	u.subtree(a, None)
+}
+
+// the pair of active indices minimizing the criterion
+fn closest<F: Fn(usize, usize) -> f32>(active: &[usize], criterion: F) -> Option<(usize, usize)> {
+	let mut best: Option<(usize, usize, f32)> = None;
+	for (a, i) in active.iter().enumerate() {
+		for j in active[a + 1..].iter() {
+			let c = criterion(*i, *j);
+			if best.is_none_or(|(_, _, b)| c < b) {
+				best = Some((*i, *j, c));
+			}
+		}
+	}
+
+	best.map(|(i, j, _)| (i, j))
+}
+
+// for each group with at least two leaves in the tree, the languages of other
+// groups inside its smallest enclosing clade; empty when the group is a clade
+pub fn intruders<'a>(tree: &'a Tree, dataset: &Dataset, grouping: Grouping) -> Vec<(String, usize, Vec<&'a str>)> {
+	let leaves = tree.leaves();
+	let clades = tree.clades();
+
+	let mut groups: HashMap<&String, Vec<&str>> = HashMap::new();
+	for leaf in leaves.iter() {
+		if let Some(group) = dataset.language(leaf).and_then(|l| grouping.of(l)) {
+			groups.entry(group).or_default().push(leaf);
+		}
+	}
+
+	let mut result = groups
+		.into_iter()
+		.filter(|(_, members)| members.len() > 1)
+		.filter_map(|(group, members)| {
+			let clade = clades
+				.iter()
+				.filter(|c| members.iter().all(|m| c.contains(m)))
+				.min_by_key(|c| c.len())?;
+			let others = clade.iter().copied().filter(|l| !members.contains(l)).collect();
+			Some((group.clone(), members.len(), others))
+		})
+		.collect::<Vec<_>>();
+	result.sort_by(|a, b| a.0.cmp(&b.0));
+
+	result
+}
+
+impl Cluster {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let comparer = Comparer::new(dataset, &self.options)?;
+		let lang_ids = match self.lang_ids.is_empty() {
+			true => {
+				let mut ids = dataset
+					.languages
+					.keys()
+					.filter(|id| comparer.values(id).is_some())
+					.cloned()
+					.collect::<Vec<_>>();
+				ids.sort();
+				ids
+			}
+			false => self.lang_ids,
+		};
+
+		let mut matrix = comparer.matrix(&lang_ids)?;
+		if self.drop_incomplete {
+			print_dropped(&matrix.drop_incomplete());
+		}
+		let tree = match self.method {
+			Method::Upgma => upgma(&matrix)?,
+			Method::Nj => {
+				let (tree, negative) = neighbor_joining(&matrix)?;
+				if negative > 0 {
+					println!("{negative} negative branch lengths set to 0, their length moved to the sister branch");
+				}
+				tree
+			}
+		};
+
+		let tree_dir = ensure_dir(out_dir.join(TREE_DIR))?;
+		let path = tree_dir.join(format!("{}.nwk", self.method.name()));
+		fs::write(&path, tree.to_newick() + "\n").map_err(|e| format!("{e} @{}", path.display()))?;
+		println!("{} languages, tree written to {}", matrix.lang_ids.len(), path.display());
+
+		let groups = intruders(&tree, dataset, self.compare);
+		let clades = groups.iter().filter(|(_, _, others)| others.is_empty()).count();
+		println!("{clades} of {} {:?} groups form a clade", groups.len(), self.compare);
+		for (group, n, others) in groups.iter().filter(|(_, _, others)| !others.is_empty()) {
+			let others = others
+				.iter()
+				.map(|id| {
+					let group = dataset.language(id).and_then(|l| self.compare.of(l));
+					format!("{id} ({})", group.map(|g| g.as_str()).unwrap_or("?"))
+				})
+				.intersperse(", ".to_string())
+				.collect::<String>();
+			println!("{group} ({n}): {others}");
+		}
+
+		plot_dendrogram(&tree, dataset, self.compare, out_dir, &format!("cluster-{}", self.method.name()))
+	}
+}
+
+#[cfg(test)]
+fn toy_matrix() -> DistanceMatrix {
+	use crate::distance::Metric;
+
+	let options = DistanceOptions {
+		metric: Metric::Hamming,
+		min_overlap: Some(1),
+		weights: None,
+		ordinal: vec![],
+	};
+	let ids = ["aaa", "aab", "bba", "cca", "ccb", "dda"].map(String::from);
+
+	Comparer::new(&crate::dataset::toy(), &options).unwrap().matrix(&ids).unwrap()
+}
+
+#[test]
+fn upgma_toy() {
+	let dataset = crate::dataset::toy();
+	let tree = upgma(&toy_matrix()).unwrap();
+
+	assert_eq!(tree.leaves().len(), 6);
+	assert!(tree.to_newick().contains("(aaa:0.000000,aab:0.000000)"));
+
+	let genera = intruders(&tree, &dataset, Grouping::Genus);
+	assert_eq!(genera.len(), 2);
+	assert!(genera.iter().all(|(_, _, others)| others.is_empty()));
+
+	// aab and dda share only P1
+	let options = DistanceOptions {
+		metric: crate::distance::Metric::Hamming,
+		min_overlap: Some(2),
+		weights: None,
+		ordinal: vec![],
+	};
+	let ids = ["aaa", "aab", "bba", "dda"].map(String::from);
+	let mut matrix = Comparer::new(&dataset, &options).unwrap().matrix(&ids).unwrap();
+	assert!(upgma(&matrix).unwrap_err().contains("--drop-incomplete"));
+	matrix.drop_incomplete();
+	assert_eq!(upgma(&matrix).unwrap().leaves().len(), 3);
+}
+
+#[test]
+fn nj_toy() {
+	let (tree, _) = neighbor_joining(&toy_matrix()).unwrap();
+	let mut leaves = tree.leaves();
+	leaves.sort();
+
+	assert_eq!(leaves, ["aaa", "aab", "bba", "cca", "ccb", "dda"]);
+	assert!(tree.to_newick().ends_with(';'));
+
+	let genera = intruders(&tree, &crate::dataset::toy(), Grouping::Genus);
+	assert!(genera.iter().all(|(_, _, others)| others.is_empty()));
+}
+
+#[test]
+fn midpoint_rooting() {
+	// ((a:1,b:1):0.5,(c:1,d:5):0.5), whose longest path a..d has its middle on d's branch
+	let pair = |x: &str, y: &str, lx: f32, ly: f32| Tree::join(vec![(Tree::leaf(x), lx), (Tree::leaf(y), ly)]);
+	let tree = Tree::join(vec![(pair("a", "b", 1.0, 1.0), 0.5), (pair("c", "d", 1.0, 5.0), 0.5)]);
+
+	let rooted = midpoint_root(tree);
+	let mut sides = rooted.children.iter().map(|(t, l)| (t.leaves(), *l)).collect::<Vec<_>>();
+	sides.sort_by_key(|(leaves, _)| leaves.len());
+	assert_eq!(sides[0], (vec!["d"], 3.5));
+	assert_eq!(sides[1].0.len(), 3);
+	assert!((sides[1].1 - 1.5).abs() < 1e-6);
+
+	// c is closer to a and b than they are to each other, which no tree fits
+	let ids = ["a", "b", "c"].map(String::from);
+	let d = [[0.0, 1.0, 0.1], [1.0, 0.0, 0.1], [0.1, 0.1, 0.0]];
+	let matrix = DistanceMatrix {
+		lang_ids: ids.to_vec(),
+		distances: d.iter().map(|row| row.iter().map(|d| Some(*d)).collect()).collect(),
+	};
+	let (tree, negative) = neighbor_joining(&matrix).unwrap();
+	assert_eq!(negative, 1);
+	assert!(!tree.to_newick().contains('-'));
+}
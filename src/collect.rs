@@ -1,11 +1,16 @@
 use std::{
 	collections::{HashMap, HashSet},
-	fs::OpenOptions,
-	io::{BufWriter, Write},
 	path::Path,
 };
 
-use crate::csvs::{read_csv, Chapter, Language, Parameter, Value};
+use serde::Serialize;
+
+use crate::{
+	bib::SourceRef,
+	csvs::{Example, Language},
+	dataset::Dataset,
+	stats::Statistics,
+};
 
 #[derive(Debug, Clone)]
 pub struct MyLanguage {
@@ -15,238 +20,263 @@ pub struct MyLanguage {
 
 #[derive(Debug, Clone)]
 pub struct SameValue {
-	pub value: usize,
+	pub value: String,
 	pub same_value_language_ids: Vec<String>,
 	pub value_language_count: usize,
-	pub language_count_total: usize,
+	pub parameter_language_count: usize, // languages coded for the parameter
+	pub frequency: f32,                  // value_language_count / parameter_language_count
+	pub language_count_total: usize,     // datapoints of all parameters
 	pub count_ratio: f32,
 }
 
 impl SameValue {
-	fn columns() -> [&'static str; 4] {
+	fn columns() -> [&'static str; 7] {
 		[
 			"value",
 			"same_value_language_ids",
 			"value_language_count",
+			"parameter_language_count",
+			"frequency",
 			"language_count_total",
+			"count_ratio",
 		]
 	}
 }
 
-type ValueLanguagesMap = HashMap<usize, HashSet<String>>; // <value, language_ids>
+type ValueLanguagesMap = HashMap<String, HashSet<String>>; // <value, language_ids>
+pub type ValueExamplesMap = HashMap<(String, String), Vec<Example>>; // <(language_id, parameter_id), examples>
 
 impl MyLanguage {
 	pub fn get_my_languages_by_id<I: IntoIterator<Item = S>, S: ToString>(
+		dataset: &Dataset,
 		lang_ids: I,
-	) -> Result<HashMap<String, Self>, String> {
-		let mylangs = get_my_languages(collect_values_per_param()?, collect_values_per_language()?)?;
+	) -> HashMap<String, Self> {
+		let mylangs = get_my_languages(dataset);
 
-		let h = lang_ids
+		lang_ids
 			.into_iter()
 			.filter_map(|s| {
 				let s = s.to_string();
 				mylangs.get(&s).map(|m| (s, m.to_owned()))
 			})
-			.collect();
-
-		Ok(h)
+			.collect()
 	}
 
-	fn columns() -> [&'static str; 10] {
-		[
-			"parameter_id",
-			"parameter_name",
-			"parameter_description",
-			"chapter_id",
-			"chapter_citation",
-			"value",
-			"same_value_language_ids",
-			"value_language_count",
-			"language_count_total",
-			"count_ratio",
-		]
-	}
+	fn rows(self, dataset: &Dataset, examples: Option<&ValueExamplesMap>) -> Vec<MyLanguageRow> {
+		let lang_id = self.language.id;
 
-	fn rows(
-		self,
-		parameters: HashMap<String, Parameter>,
-		chapters: HashMap<usize, Chapter>,
-	) -> Vec<[String; 10]> {
 		self
 			.param_values
 			.into_iter()
 			.filter_map(|(parameter_id, samevalue)| {
-				let p = parameters.get(&parameter_id)?.clone();
-				let ch = chapters.get(&p.chapter_id)?;
+				let p = dataset.parameters.get(&parameter_id)?.clone();
+				let ch = p.chapter_id.and_then(|id| dataset.chapters.get(&id));
 
 				let mut sames = samevalue.same_value_language_ids;
 				sames.sort();
-
-				Some([
+				let value_label = dataset.codes.label(&parameter_id, &samevalue.value);
+
+				let examples = examples.map(|examples| {
+					examples
+						.get(&(lang_id.clone(), parameter_id.clone()))
+						.map(|es| {
+							es.iter()
+								.map(|e| e.oneline())
+								.intersperse(" / ".to_string())
+								.collect::<String>()
+						})
+						.unwrap_or_default()
+				});
+
+				Some(MyLanguageRow {
 					parameter_id,
-					p.name.replace(",", ";"),
-					p.description.replace(",", ";"),
-					p.chapter_id.to_string(),
-					ch.citation.replace(",", ";"),
-					samevalue.value.to_string(),
-					sames
-						.into_iter()
-						.intersperse(" ".to_string())
-						.collect::<String>(),
-					samevalue.value_language_count.to_string(),
-					samevalue.language_count_total.to_string(),
-					samevalue.count_ratio.to_string(),
-				])
+					parameter_name: p.name,
+					parameter_description: p.description,
+					chapter_id: p.chapter_id,
+					chapter_citation: ch.map(|ch| ch.citation.clone()).unwrap_or_default(),
+					value: samevalue.value,
+					value_label,
+					same_value_language_ids: sames.join(" "),
+					value_language_count: samevalue.value_language_count,
+					parameter_language_count: samevalue.parameter_language_count,
+					frequency: samevalue.frequency,
+					language_count_total: samevalue.language_count_total,
+					count_ratio: samevalue.count_ratio,
+					examples,
+				})
 			})
 			.collect()
 	}
 
-	pub fn write_to_csv<P: AsRef<Path>>(self, path: P) -> Result<(), String> {
-		let columns = Self::columns()
-			.into_iter()
-			.intersperse(",")
-			.collect::<String>();
-
-		let parameters = read_csv::<Parameter, _>("parameters.csv")?
-			.into_iter()
-			.map(|l| (l.id.clone(), l))
-			.collect();
-
-		let chapters = read_csv::<Chapter, _>("chapters.csv")?
-			.into_iter()
-			.filter_map(|l| l.id.parse::<usize>().map(|id| (id, l)).ok())
-			.collect();
-
-		let rows = self
-			.rows(parameters, chapters)
-			.into_iter()
-			.map(|row| {
-				row
-					.into_iter()
-					.intersperse(",".to_string())
-					.collect::<String>()
+	pub fn write_to_csv<P: AsRef<Path>>(
+		self,
+		dataset: &Dataset,
+		path: P,
+		examples: Option<&ValueExamplesMap>,
+	) -> Result<(), String> {
+		let mut rows = self.rows(dataset, examples);
+		rows.sort_by(|a, b| a.parameter_id.cmp(&b.parameter_id));
+
+		let path = path.as_ref();
+		let mut w = csv::Writer::from_path(path)
+			.map_err(|e| format!("{e} @{}", path.display()))?;
+
+		let errors = rows
+			.iter()
+			.filter_map(|row| {
+				w.serialize(row)
+					.err()
+					.map(|e| format!("{e} @{}/{}", path.display(), row.parameter_id))
 			})
 			.collect::<Vec<_>>();
 
-		let mut lines = vec![columns];
-		lines.extend(rows.into_iter());
-
-		let f = OpenOptions::new()
-			.truncate(true)
-			.create(true)
-			.write(true)
-			.open(path)
-			.map_err(|e| e.to_string())?;
-
-		let mut b = BufWriter::new(f);
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
 
-		for line in lines {
-			match b.write_all((line + "\n").as_bytes()) {
-				Ok(_) => {}
-				Err(e) => print!("{e}"),
-			}
+		match errors.is_empty() {
+			true => Ok(()),
+			false => Err(errors.join("\n")),
 		}
-
-		Ok(())
 	}
 }
 
-pub fn get_my_languages(
-	collected_values_per_param: HashMap<String, ValueLanguagesMap>,
-	collected_values_per_lang: HashMap<String, ParamValueMap>,
-) -> Result<HashMap<String, MyLanguage>, String> {
-	let langs: Vec<Language> = read_csv("languages.csv")?;
+#[derive(Debug, Clone, Serialize)]
+struct MyLanguageRow {
+	parameter_id: String,
+	parameter_name: String,
+	parameter_description: String,
+	chapter_id: Option<usize>,
+	chapter_citation: String,
+	value: String,
+	value_label: String,
+	same_value_language_ids: String,
+	value_language_count: usize,
+	parameter_language_count: usize,
+	frequency: f32,
+	language_count_total: usize,
+	count_ratio: f32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	examples: Option<String>,
+}
 
-	Ok(
-		langs
-			.into_iter()
-			.filter_map(|lang| {
-				let language = lang.clone();
-				let paramvalue = collected_values_per_lang.get(&lang.id)?;
-
-				let param_values: HashMap<String, SameValue> = paramvalue
-					.into_iter()
-					.filter_map(|(param_id, value)| {
-						let value_langs = collected_values_per_param.get(param_id)?;
-						let total: usize = collected_values_per_param
-							.iter()
-							.map(|(_, valuelangs)| valuelangs.values().map(|s| s.len()).sum::<usize>())
-							.sum();
-						let sames = value_langs.get(value)?;
-						let count = sames.len();
-						let sames = sames
-							.into_iter()
-							.filter(|lang_id| lang_id.to_string() != lang.id)
-							.cloned()
-							.collect::<Vec<_>>();
-
-						let samevalue: SameValue = SameValue {
-							value: *value,
-							same_value_language_ids: sames,
-							value_language_count: count,
-							language_count_total: total,
-							count_ratio: count as f32 / total as f32,
-						};
-
-						Some((param_id.to_owned(), samevalue))
-					})
-					.collect();
-
-				Some((
-					lang.id,
-					MyLanguage {
-						language,
-						param_values,
-					},
-				))
-			})
-			.collect(),
-	)
+pub fn get_my_languages(dataset: &Dataset) -> HashMap<String, MyLanguage> {
+	let collected_values_per_lang = collect_values_per_language(dataset);
+	let stats = Statistics::new(dataset);
+
+	dataset
+		.languages
+		.values()
+		.filter_map(|lang| {
+			let language = lang.clone();
+			let paramvalue = collected_values_per_lang.get(&lang.id)?;
+
+			let param_values: HashMap<String, SameValue> = paramvalue
+				.iter()
+				.filter_map(|(param_id, value)| {
+					let coded = stats.parameter(param_id)?.coded;
+					let sames = dataset.languages_with_value(param_id, value)?;
+					let count = sames.len();
+					let sames = sames
+						.iter()
+						.filter(|lang_id| lang_id.to_string() != lang.id)
+						.cloned()
+						.collect::<Vec<_>>();
+
+					let samevalue: SameValue = SameValue {
+						value: value.to_owned(),
+						same_value_language_ids: sames,
+						value_language_count: count,
+						parameter_language_count: coded,
+						frequency: count as f32 / coded as f32,
+						language_count_total: stats.datapoints,
+						count_ratio: count as f32 / stats.datapoints as f32,
+					};
+
+					Some((param_id.to_owned(), samevalue))
+				})
+				.collect();
+
+			Some((
+				lang.id.clone(),
+				MyLanguage {
+					language,
+					param_values,
+				},
+			))
+		})
+		.collect()
 }
 
-pub fn collect_values_per_param() -> Result<HashMap<String, ValueLanguagesMap>, String> {
-	let values: Vec<Value> = read_csv("values.csv")?;
+pub fn collect_values_per_param(dataset: &Dataset) -> HashMap<String, ValueLanguagesMap> {
+	dataset
+		.parameters
+		.keys()
+		.filter_map(|p| Some((p.clone(), dataset.value_languages(p)?.clone())))
+		.collect()
+}
 
-	let mut rares: HashMap<String, ValueLanguagesMap> = HashMap::new();
+pub type ParamValueMap = HashMap<String, String>;
 
-	for value in values {
-		rares
-			.entry(value.parameter_id)
-			.and_modify(|h| {
-				h.entry(value.value)
-					.and_modify(|set| {
-						set.insert(value.language_id.clone());
-					})
-					.or_insert(HashSet::from_iter([value.language_id.clone()]));
-			})
-			.or_insert(HashMap::from_iter([(
-				value.value,
-				HashSet::from_iter([value.language_id]),
-			)]));
+pub fn collect_values_per_language(dataset: &Dataset) -> HashMap<String, ParamValueMap> {
+	let mut hm: HashMap<String, ParamValueMap> = HashMap::new();
+
+	for value in dataset.values.iter() {
+		let Some(v) = &value.value else {
+			continue;
+		};
+
+		hm.entry(value.language_id.clone())
+			.or_default()
+			.insert(value.parameter_id.clone(), v.clone());
 	}
 
-	Ok(rares)
+	hm
 }
 
-type ParamValueMap = HashMap<String, usize>;
+pub fn collect_examples_per_value(dataset: &Dataset) -> ValueExamplesMap {
+	let mut hm: ValueExamplesMap = HashMap::new();
 
-pub fn collect_values_per_language() -> Result<HashMap<String, ParamValueMap>, String> {
-	let values: Vec<Value> = read_csv("values.csv")?;
+	for value in dataset.values.iter() {
+		let linked = value
+			.example_ids
+			.iter()
+			.filter_map(|id| dataset.examples.get(id).cloned())
+			.collect::<Vec<_>>();
 
-	let mut hm: HashMap<String, ParamValueMap> = HashMap::new();
+		if !linked.is_empty() {
+			hm.entry((value.language_id.clone(), value.parameter_id.clone()))
+				.or_default()
+				.extend(linked);
+		}
+	}
 
-	for value in values {
-		hm.entry(value.language_id)
-			.and_modify(|h| {
-				h.entry(value.parameter_id.clone())
-					.and_modify(|set| {
-						*set = value.value;
-					})
-					.or_insert(value.value);
-			})
-			.or_insert(HashMap::from_iter([(value.parameter_id, value.value)]));
+	hm
+}
+
+// sources of the language itself followed by those of each of its values
+pub fn collect_sources_per_language(dataset: &Dataset) -> HashMap<String, Vec<SourceRef>> {
+	let mut hm: HashMap<String, Vec<SourceRef>> = dataset
+		.languages
+		.values()
+		.map(|l| (l.id.clone(), l.sources.clone()))
+		.collect();
+
+	for value in dataset.values.iter() {
+		hm.entry(value.language_id.clone())
+			.or_default()
+			.extend(value.sources.iter().cloned());
 	}
 
-	Ok(hm)
+	hm
+}
+
+#[test]
+fn same_value_frequency() {
+	let mylangs = get_my_languages(&crate::dataset::toy());
+	let p2 = &mylangs["bba"].param_values["P2"];
+
+	assert_eq!(p2.value_language_count, 3);
+	assert_eq!(p2.parameter_language_count, 5);
+	assert_eq!(p2.frequency, 0.6);
+	assert_eq!(p2.language_count_total, 15);
+	assert_eq!(p2.same_value_language_ids.len(), 2);
 }
-// }
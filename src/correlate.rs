@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use clap::Args;
+
+use crate::{
+	collect::{collect_values_per_language, ParamValueMap},
+	dataset::Dataset,
+	stats::Weighting,
+};
+
+/// Test the association of two parameters, with genealogical and areal control
+#[derive(Debug, Args)]
+pub struct Correlate {
+	param_a: String,
+	param_b: String,
+}
+
+// counts of each pair of values, possibly fractional when groups of languages
+// share one vote
+#[derive(Debug, Clone, Default)]
+pub struct Contingency {
+	pub rows: Vec<String>,
+	pub cols: Vec<String>,
+	pub counts: Vec<Vec<f64>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Association {
+	pub n: f64,
+	pub chi_square: f64,
+	pub df: usize,
+	pub p: f64,
+	pub cramers_v: f64,
+	pub mutual_information: f64, // bits
+}
+
+impl Contingency {
+	pub fn new<'a, I: IntoIterator<Item = (&'a str, &'a str, f64)>>(pairs: I) -> Self {
+		let pairs = pairs.into_iter().collect::<Vec<_>>();
+		let sorted = |mut v: Vec<String>| {
+			v.sort_by(|a, b| match (a.parse::<f64>(), b.parse::<f64>()) {
+				(Ok(x), Ok(y)) => x.total_cmp(&y),
+				_ => a.cmp(b),
+			});
+			v.dedup();
+			v
+		};
+		let rows = sorted(pairs.iter().map(|(a, _, _)| a.to_string()).collect());
+		let cols = sorted(pairs.iter().map(|(_, b, _)| b.to_string()).collect());
+
+		let mut counts = vec![vec![0.0; cols.len()]; rows.len()];
+		for (a, b, w) in pairs {
+			let i = rows.iter().position(|r| r == a).unwrap_or_default();
+			let j = cols.iter().position(|c| c == b).unwrap_or_default();
+			counts[i][j] += w;
+		}
+
+		Self { rows, cols, counts }
+	}
+
+	pub fn n(&self) -> f64 {
+		self.counts.iter().flatten().fold(0.0, |s, c| s + c)
+	}
+
+	fn margins(&self) -> (Vec<f64>, Vec<f64>) {
+		let row_sums = self.counts.iter().map(|r| r.iter().sum()).collect();
+		let col_sums = (0..self.cols.len())
+			.map(|j| self.counts.iter().map(|r| r[j]).sum())
+			.collect();
+		(row_sums, col_sums)
+	}
+
+	pub fn association(&self) -> Option<Association> {
+		let n = self.n();
+		if n <= 0.0 || self.rows.len() < 2 || self.cols.len() < 2 {
+			return None;
+		}
+
+		let (row_sums, col_sums) = self.margins();
+		let (mut chi_square, mut mutual_information) = (0.0, 0.0);
+		for (i, row) in self.counts.iter().enumerate() {
+			for (j, observed) in row.iter().enumerate() {
+				let expected = row_sums[i] * col_sums[j] / n;
+				if expected > 0.0 {
+					chi_square += (observed - expected).powi(2) / expected;
+				}
+				if *observed > 0.0 {
+					mutual_information += observed / n * (observed / expected).log2();
+				}
+			}
+		}
+
+		let df = (self.rows.len() - 1) * (self.cols.len() - 1);
+		let k = self.rows.len().min(self.cols.len()) - 1;
+
+		Some(Association {
+			n,
+			chi_square,
+			df,
+			p: chi_square_p(chi_square, df),
+			cramers_v: (chi_square / (n * k as f64)).sqrt(),
+			mutual_information,
+		})
+	}
+}
+
+// upper tail probability of the chi-square distribution
+pub fn chi_square_p(x: f64, df: usize) -> f64 {
+	if x <= 0.0 {
+		return 1.0;
+	}
+	gamma_q(df as f64 / 2.0, x / 2.0)
+}
+
+// regularized upper incomplete gamma function, by series below a + 1 and by
+// continued fraction above
+fn gamma_q(a: f64, x: f64) -> f64 {
+	let ln_prefix = a * x.ln() - x - ln_gamma(a);
+
+	if x < a + 1.0 {
+		let (mut term, mut sum, mut ap) = (1.0 / a, 1.0 / a, a);
+		for _ in 0..500 {
+			ap += 1.0;
+			term *= x / ap;
+			sum += term;
+			if term.abs() < sum.abs() * 1e-15 {
+				break;
+			}
+		}
+		return (1.0 - sum * ln_prefix.exp()).clamp(0.0, 1.0);
+	}
+
+	let tiny = 1e-300;
+	let mut b = x + 1.0 - a;
+	let mut c = 1.0 / tiny;
+	let mut d = 1.0 / b;
+	let mut h = d;
+	for i in 1..500 {
+		let an = -(i as f64) * (i as f64 - a);
+		b += 2.0;
+		d = an * d + b;
+		if d.abs() < tiny {
+			d = tiny;
+		}
+		c = b + an / c;
+		if c.abs() < tiny {
+			c = tiny;
+		}
+		d = 1.0 / d;
+		let delta = d * c;
+		h *= delta;
+		if (delta - 1.0).abs() < 1e-15 {
+			break;
+		}
+	}
+
+	(ln_prefix.exp() * h).clamp(0.0, 1.0)
+}
+
+// Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+	const G: [f64; 6] = [
+		76.18009172947146,
+		-86.50532032941677,
+		24.01409824083091,
+		-1.231739572450155,
+		0.1208650973866179e-2,
+		-0.5395239384953e-5,
+	];
+
+	let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+	let series = G
+		.iter()
+		.enumerate()
+		.fold(1.000000000190015, |s, (i, g)| s + g / (x + 1.0 + i as f64));
+
+	-tmp + (2.5066282746310005 * series / x).ln()
+}
+
+impl Correlate {
+	pub fn print(self, dataset: &Dataset) -> Result<(), String> {
+		for p in [&self.param_a, &self.param_b] {
+			if !dataset.parameters.contains_key(p) {
+				return Err(format!("parameter {p} not found"));
+			}
+		}
+
+		let values = collect_values_per_language(dataset);
+		let all = self.contingency(dataset, &values, Weighting::None, None);
+		if all.n() == 0.0 {
+			return Err(format!("no language is coded for both {} and {}", self.param_a, self.param_b));
+		}
+
+		println!("{} x {}", self.param_a, self.param_b);
+		for (i, row) in all.counts.iter().enumerate() {
+			let cells = row
+				.iter()
+				.map(|c| format!("{c:>6}"))
+				.collect::<String>();
+			println!("{cells}  {}", dataset.codes.label(&self.param_a, &all.rows[i]));
+		}
+		let header = all.cols.iter().map(|c| format!("{c:>6}")).collect::<String>();
+		println!("{header}  <- {}\n", self.param_b);
+
+		println!(
+			"{:<24} {:>8} {:>10} {:>4} {:>10} {:>8} {:>8}",
+			"sample", "n", "chi2", "df", "p", "V", "MI"
+		);
+
+		let mut scopes: Vec<(String, Weighting, Option<&String>)> = vec![
+			("languages".to_string(), Weighting::None, None),
+			("one vote per genus".to_string(), Weighting::Genus, None),
+			("one vote per family".to_string(), Weighting::Family, None),
+		];
+		for macroarea in dataset.macroareas() {
+			scopes.push((macroarea.clone(), Weighting::None, Some(macroarea)));
+		}
+
+		for (name, weighting, macroarea) in scopes {
+			let table = self.contingency(dataset, &values, weighting, macroarea);
+			if table.n() == 0.0 {
+				continue;
+			}
+
+			match table.association() {
+				Some(a) => println!(
+					"{:<24} {:>8.1} {:>10.3} {:>4} {:>10.2e} {:>8.3} {:>8.3}",
+					name, a.n, a.chi_square, a.df, a.p, a.cramers_v, a.mutual_information
+				),
+				None => println!("{:<24} {:>8.1} {:>10}", name, table.n(), "-"),
+			}
+		}
+
+		Ok(())
+	}
+
+	// languages coded for both parameters, each group of the weighting sharing one vote
+	fn contingency(
+		&self,
+		dataset: &Dataset,
+		values: &HashMap<String, ParamValueMap>,
+		weighting: Weighting,
+		macroarea: Option<&String>,
+	) -> Contingency {
+		let pairs = values
+			.iter()
+			.filter(|(lang_id, _)| {
+				macroarea.is_none() || dataset.language(lang_id).and_then(|l| l.macroarea.as_ref()) == macroarea
+			})
+			.filter_map(|(lang_id, pv)| Some((lang_id.as_str(), pv.get(&self.param_a)?, pv.get(&self.param_b)?)))
+			.collect::<Vec<_>>();
+
+		let mut group_sizes: HashMap<&str, usize> = HashMap::new();
+		for (lang_id, _, _) in pairs.iter() {
+			*group_sizes.entry(weighting.group(dataset, lang_id)).or_default() += 1;
+		}
+
+		Contingency::new(pairs.iter().map(|(lang_id, a, b)| {
+			let w = 1.0 / group_sizes[weighting.group(dataset, lang_id)] as f64;
+			(a.as_str(), b.as_str(), w)
+		}))
+	}
+}
+
+#[test]
+fn association_toy() {
+	let dataset = crate::dataset::toy();
+	let correlate = Correlate {
+		param_a: "P1".to_string(),
+		param_b: "P2".to_string(),
+	};
+	let values = collect_values_per_language(&dataset);
+
+	let table = correlate.contingency(&dataset, &values, Weighting::None, None);
+	assert_eq!(table.counts, [[2.0, 1.0], [0.0, 2.0]]);
+
+	let a = table.association().unwrap();
+	assert!((a.chi_square - 20.0 / 9.0).abs() < 1e-9);
+	assert!((a.cramers_v - 2.0 / 3.0).abs() < 1e-9);
+	assert!((a.p - 0.136).abs() < 1e-3);
+	assert!((chi_square_p(3.841, 1) - 0.05).abs() < 1e-3);
+
+	let genus = correlate.contingency(&dataset, &values, Weighting::Genus, None);
+	assert_eq!(genus.n(), 3.0);
+}
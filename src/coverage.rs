@@ -0,0 +1,287 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs,
+	path::Path,
+};
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{dataset::Dataset, util::ensure_dir};
+
+const PARAMETERS_FILE: &str = "out/coverage-parameters.csv";
+const LANGUAGES_FILE: &str = "out/coverage-languages.csv";
+const DENSE_LANGUAGES_FILE: &str = "out/dense-languages.txt";
+
+/// Report how many languages code each parameter (per macroarea) and how many parameters each
+/// language codes, and find a large language by parameter subset of at least --density
+#[derive(Debug, Args)]
+pub struct Coverage {
+	/// Share of coded cells, in percent, the subset has to reach
+	#[arg(long, default_value_t = 80.0)]
+	density: f32,
+
+	/// Only consider these parameters for the subset, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	/// Number of parameters and languages printed after writing the tables
+	#[arg(long, default_value_t = 10)]
+	top: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageCoverage {
+	language_id: String,
+	language_name: String,
+	family: String,
+	macroarea: String,
+	parameters: usize,
+	share: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DenseSubset {
+	pub lang_ids: Vec<String>,
+	pub param_ids: Vec<String>,
+	pub coded: usize,
+}
+
+impl DenseSubset {
+	pub fn cells(&self) -> usize {
+		self.lang_ids.len() * self.param_ids.len()
+	}
+
+	pub fn density(&self) -> f32 {
+		self.coded as f32 / self.cells().max(1) as f32
+	}
+}
+
+impl Coverage {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+		if !(0.0..=100.0).contains(&self.density) {
+			return Err(format!("--density {} is not a percentage", self.density));
+		}
+
+		let coded = coded_cells(dataset);
+		let macroareas = dataset.macroareas();
+
+		// per parameter, with one column per macroarea
+		let mut param_ids = dataset.parameters.keys().collect::<Vec<_>>();
+		param_ids.sort();
+		let mut param_rows = param_ids
+			.iter()
+			.map(|param_id| {
+				let langs = coded.iter().filter(|(_, ps)| ps.contains(*param_id)).map(|(l, _)| l);
+				let mut per_macroarea: HashMap<&str, usize> = HashMap::new();
+				let mut n = 0;
+				for lang_id in langs {
+					n += 1;
+					if let Some(m) = dataset.language(lang_id).and_then(|l| l.macroarea.as_deref()) {
+						*per_macroarea.entry(m).or_default() += 1;
+					}
+				}
+
+				let mut row = vec![
+					param_id.to_string(),
+					dataset.parameters[*param_id].name.clone(),
+					n.to_string(),
+				];
+				row.extend(
+					macroareas
+						.iter()
+						.map(|m| per_macroarea.get(m.as_str()).copied().unwrap_or(0).to_string()),
+				);
+				(n, row)
+			})
+			.collect::<Vec<_>>();
+		param_rows.sort_by(|a, b| b.0.cmp(&a.0).then(a.1[0].cmp(&b.1[0])));
+
+		let header = ["parameter_id", "parameter_name", "languages"]
+			.into_iter()
+			.map(String::from)
+			.chain(macroareas.iter().map(|m| m.to_string()))
+			.collect::<Vec<_>>();
+		let path = out_dir.join(PARAMETERS_FILE);
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in std::iter::once(&header).chain(param_rows.iter().map(|(_, r)| r)) {
+			w.write_record(row).map_err(|e| format!("{e} @{}", path.display()))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		// per language
+		let lang_rows = language_coverage(dataset, &coded);
+		let path = out_dir.join(LANGUAGES_FILE);
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in lang_rows.iter() {
+			w.serialize(row)
+				.map_err(|e| format!("{e} @{}/{}", path.display(), row.language_id))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!("best covered parameters:");
+		for (n, row) in param_rows.iter().take(self.top) {
+			println!("{:>8} {:<50} {:>5}", row[0], row[1], n);
+		}
+		println!("best covered languages:");
+		for row in lang_rows.iter().take(self.top) {
+			println!("{:>8} {:<50} {:>5}", row.language_id, row.language_name, row.parameters);
+		}
+		println!(
+			"{} parameters written to {}, {} languages to {}",
+			param_rows.len(),
+			out_dir.join(PARAMETERS_FILE).display(),
+			lang_rows.len(),
+			path.display()
+		);
+
+		// densest subset
+		let candidates = match self.params.is_empty() {
+			true => dataset.parameters.keys().cloned().collect(),
+			false => self.params.iter().cloned().collect(),
+		};
+		let subset = dense_subset(&coded, &candidates, self.density / 100.0);
+
+		let path = out_dir.join(DENSE_LANGUAGES_FILE);
+		fs::write(&path, subset.lang_ids.join("\n") + "\n").map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!(
+			"{} languages x {} parameters at {:.1}% density, languages written to {}",
+			subset.lang_ids.len(),
+			subset.param_ids.len(),
+			100.0 * subset.density(),
+			path.display()
+		);
+		println!("--params {}", subset.param_ids.join(","));
+
+		Ok(())
+	}
+}
+
+// <language_id, coded parameter_ids>; values marked unknown do not count
+fn coded_cells(dataset: &Dataset) -> HashMap<String, HashSet<String>> {
+	let mut coded: HashMap<String, HashSet<String>> = HashMap::new();
+	for v in dataset.values.iter().filter(|v| v.value.is_some()) {
+		coded
+			.entry(v.language_id.clone())
+			.or_default()
+			.insert(v.parameter_id.clone());
+	}
+
+	coded
+}
+
+// best covered first
+fn language_coverage(dataset: &Dataset, coded: &HashMap<String, HashSet<String>>) -> Vec<LanguageCoverage> {
+	// WALS's family and genus rows have no values
+	let mut rows = dataset
+		.languages
+		.values()
+		.filter(|lang| !dataset.values_of_language(&lang.id).is_empty())
+		.map(|lang| {
+			let parameters = coded.get(&lang.id).map(|ps| ps.len()).unwrap_or(0);
+			LanguageCoverage {
+				language_id: lang.id.clone(),
+				language_name: lang.name.clone(),
+				family: lang.family.clone().unwrap_or_default(),
+				macroarea: lang.macroarea.clone().unwrap_or_default(),
+				parameters,
+				share: parameters as f32 / dataset.parameters.len().max(1) as f32,
+			}
+		})
+		.collect::<Vec<_>>();
+
+	rows.sort_by(|a, b| b.parameters.cmp(&a.parameters).then_with(|| a.language_id.cmp(&b.language_id)));
+	rows
+}
+
+// greedy peeling: while the matrix is too sparse, drop the language or parameter
+// with the smallest share of coded cells in its row or column
+pub fn dense_subset(
+	coded: &HashMap<String, HashSet<String>>,
+	param_ids: &HashSet<String>,
+	density: f32,
+) -> DenseSubset {
+	let mut langs: HashMap<&String, usize> = coded
+		.iter()
+		.map(|(l, ps)| (l, ps.intersection(param_ids).count()))
+		.filter(|(_, n)| *n > 0)
+		.collect();
+	let mut params: HashMap<&String, usize> = HashMap::new();
+	for ps in coded.values() {
+		for p in ps.intersection(param_ids) {
+			*params.entry(p).or_default() += 1;
+		}
+	}
+	let mut total: usize = langs.values().sum();
+
+	while !langs.is_empty() && (total as f32) < density * (langs.len() * params.len()) as f32 {
+		let worst_lang = langs
+			.iter()
+			.map(|(l, n)| (*n as f32 / params.len() as f32, *l))
+			.min_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(a.1)));
+		let worst_param = params
+			.iter()
+			.map(|(p, n)| (*n as f32 / langs.len() as f32, *p))
+			.min_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(a.1)));
+
+		match (worst_lang, worst_param) {
+			(Some((lf, l)), Some((pf, _))) if lf <= pf => {
+				total -= langs.remove(l).unwrap_or(0);
+				for p in coded[l].iter() {
+					if let Some(n) = params.get_mut(p) {
+						*n -= 1;
+					}
+				}
+			}
+			(_, Some((_, p))) => {
+				total -= params.remove(p).unwrap_or(0);
+				for (l, n) in langs.iter_mut() {
+					if coded[*l].contains(p) {
+						*n -= 1;
+					}
+				}
+			}
+			_ => break,
+		}
+		params.retain(|_, n| *n > 0);
+		langs.retain(|_, n| *n > 0);
+	}
+
+	let mut lang_ids = langs.keys().map(|l| l.to_string()).collect::<Vec<_>>();
+	lang_ids.sort();
+	let mut param_ids = params.keys().map(|p| p.to_string()).collect::<Vec<_>>();
+	param_ids.sort();
+
+	DenseSubset {
+		lang_ids,
+		param_ids,
+		coded: total,
+	}
+}
+
+#[test]
+fn dense_toy() {
+	let dataset = crate::dataset::toy();
+	let coded = coded_cells(&dataset);
+	let all = dataset.parameters.keys().cloned().collect::<HashSet<_>>();
+
+	let full = dense_subset(&coded, &all, 0.0);
+	assert_eq!(full.lang_ids.len(), coded.len());
+	assert_eq!(full.coded, coded.values().map(|ps| ps.len()).sum::<usize>());
+
+	let dense = dense_subset(&coded, &all, 1.0);
+	assert_eq!(dense.density(), 1.0);
+	assert!(dense.lang_ids.iter().all(|l| dense.param_ids.iter().all(|p| coded[l].contains(p))));
+
+	let rows = language_coverage(&dataset, &coded);
+	assert!(rows.windows(2).all(|w| w[0].parameters >= w[1].parameters));
+	assert_eq!(rows.len(), 6);
+	assert!(rows.iter().all(|r| r.language_id != "family-f1"));
+}
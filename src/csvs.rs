@@ -1,32 +1,36 @@
-use core::borrow;
-use std::{
-	fmt::{Debug, Display},
-	fs::File,
-	path::Path,
-};
-
-use csv::{StringRecord, StringRecordIter};
+use std::{collections::HashMap, fmt::Debug, fs::File};
+
 use serde::Deserialize;
 
-const PATH_PREFIX: &str = "cldf/";
+use crate::bib::SourceRef;
+use crate::metadata::{Columns, Component, Metadata, Record};
+
+pub trait TableRow {
+	const COMPONENT: Component;
+}
 
-pub fn read_csv<Row: TryFrom<StringRecord>, P: AsRef<Path> + Display>(
-	path: P,
-) -> Result<Vec<Row>, String>
+pub fn read_table<Row>(metadata: &Metadata) -> Result<Vec<Row>, String>
 where
-	<Row as TryFrom<StringRecord>>::Error: Debug,
+	Row: TableRow + for<'a> TryFrom<Record<'a>, Error = String>,
 {
-	let f = File::open(format!("{}{}", PATH_PREFIX, path))
-		.map_err(|e| format!("{e} @File::open/read_csv"))?;
+	let table = metadata
+		.table(Row::COMPONENT)
+		.ok_or_else(|| format!("no {} in metadata @read_table", Row::COMPONENT.term()))?;
+
+	let f = File::open(metadata.table_path(table))
+		.map_err(|e| format!("{e} @File::open/read_table"))?;
 
 	let mut rdr = csv::Reader::from_reader(f);
+	let headers = rdr.headers().map_err(|e| format!("{e} @headers/read_table"))?.clone();
+	let columns = Columns::new(table, &headers);
+
 	let mut rows: Vec<Row> = vec![];
 	for row in rdr.records() {
 		match row {
 			Err(e) => {
 				println!("{e}");
 			}
-			Ok(o) => match o.try_into() {
+			Ok(record) => match (Record { record: &record, columns: &columns }).try_into() {
 				Ok(ok) => {
 					rows.push(ok);
 				},
@@ -40,13 +44,90 @@ where
 	Ok(rows)
 }
 
+// for components a dataset may legitimately lack, e.g. chapters outside WALS
+pub fn read_optional_table<Row>(metadata: &Metadata) -> Result<Vec<Row>, String>
+where
+	Row: TableRow + for<'a> TryFrom<Record<'a>, Error = String>,
+{
+	match metadata.table(Row::COMPONENT) {
+		Some(_) => read_table(metadata),
+		None => Ok(vec![]),
+	}
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Parameter {
 	pub id: String,
 	pub name: String,
 	pub description: String,
 	pub column_spec: String,
-	pub chapter_id: usize,
+	pub chapter_id: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Code {
+	pub id: String,
+	pub parameter_id: String,
+	pub name: String,
+	pub description: String,
+	pub number: Option<usize>,
+}
+
+impl Code {
+	// the string a Value of this code carries: the number in WALS, the name otherwise
+	pub fn value(&self) -> String {
+		self.number
+			.map(|n| n.to_string())
+			.unwrap_or_else(|| self.name.clone())
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Codes {
+	codes: HashMap<(String, String), Code>, // <(parameter_id, value), code>
+}
+
+impl Codes {
+	pub fn new<I: IntoIterator<Item = Code>>(codes: I) -> Self {
+		let codes = codes
+			.into_iter()
+			.map(|c| ((c.parameter_id.clone(), c.value()), c))
+			.collect();
+
+		Self { codes }
+	}
+
+	pub fn read(metadata: &Metadata) -> Result<Self, String> {
+		read_optional_table::<Code>(metadata).map(Self::new)
+	}
+
+	pub fn get(&self, parameter_id: &str, value: &str) -> Option<&Code> {
+		self.codes.get(&(parameter_id.to_string(), value.to_string()))
+	}
+
+	pub fn name(&self, parameter_id: &str, value: &str) -> Option<&str> {
+		self.get(parameter_id, value).map(|c| c.name.as_str())
+	}
+
+	// "3 = Verb-Object"; falls back to the description when the name is the value
+	// itself (e.g. Grambank's "0" / "1"), and to the bare value without a code
+	pub fn label(&self, parameter_id: &str, value: &str) -> String {
+		match self.get(parameter_id, value) {
+			Some(c) if c.name != value => format!("{} = {}", value, c.name),
+			Some(c) if !c.description.is_empty() => format!("{} = {}", value, c.description),
+			_ => value.to_string(),
+		}
+	}
+
+	pub fn of_parameter(&self, parameter_id: &str) -> Vec<&Code> {
+		let mut codes = self
+			.codes
+			.values()
+			.filter(|c| c.parameter_id == parameter_id)
+			.collect::<Vec<_>>();
+		codes.sort_by(|a, b| a.number.cmp(&b.number).then(a.name.cmp(&b.name)));
+		codes
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -54,59 +135,93 @@ pub struct Value {
 	pub id: String,
 	pub language_id: String,
 	pub parameter_id: String,
-	pub value: usize,
-	pub code_id: String,
+	pub value: Option<String>, // None for rows marked unknown, e.g. "?" in Grambank
+	pub code_id: Option<String>,
 	pub comment: String,
-	pub source: String,
-	pub example_id: String,
+	pub sources: Vec<SourceRef>,
+	pub example_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
-pub struct Language {
+pub struct Example {
 	pub id: String,
-	pub name: String,
-	pub macroarea: String,
-	pub latitude: f32,
-	pub longitude: f32,
-	pub glottocode: String,
-	pub iso6393p3code: String,
-	pub family: String,
-	pub subfamily: String,
-	pub genus: String,
-	pub genus_icon: String,
-	pub iso_codes: String,
-	pub samples_100: bool,
-	pub samples_200: bool,
-	pub country_id: String,
-	pub source: String,
-	pub parent_id: String,
+	pub language_id: String,
+	pub primary_text: String,
+	pub analyzed_words: Vec<String>,
+	pub glosses: Vec<String>,
+	pub translated_text: String,
+	pub meta_language_id: Option<String>,
+	pub comment: String,
 }
 
-impl Language {
-	pub fn columns() -> [&'static str; 17] {
-		[
-			"id",
-			"name",
-			"macroarea",
-			"latitude",
-			"longitude",
-			"glottocode",
-			"iso6393p3code",
-			"family",
-			"subfamily",
-			"genus",
-			"genus_icon",
-			"iso_codes",
-			"samples_100",
-			"samples_200",
-			"country_id",
-			"source",
-			"parent_id",
-		]
+impl Example {
+	// analyzed words and glosses padded into aligned columns
+	pub fn interlinear(&self) -> String {
+		let n = self.analyzed_words.len().max(self.glosses.len());
+		let (mut words, mut glosses) = (String::new(), String::new());
+
+		for i in 0..n {
+			let word = self.analyzed_words.get(i).map(|s| s.as_str()).unwrap_or_default();
+			let gloss = self.glosses.get(i).map(|s| s.as_str()).unwrap_or_default();
+			let width = word.chars().count().max(gloss.chars().count()) + 1;
+
+			words += &format!("{:<width$}", word);
+			glosses += &format!("{:<width$}", gloss);
+		}
+
+		let mut lines = vec![];
+		if !self.primary_text.is_empty() {
+			lines.push(self.primary_text.clone());
+		}
+		if n > 0 {
+			lines.push(words.trim_end().to_string());
+			lines.push(glosses.trim_end().to_string());
+		}
+		if !self.translated_text.is_empty() {
+			lines.push(format!("'{}'", self.translated_text));
+		}
+
+		lines.join("\n")
+	}
+
+	// single line form for tabular output
+	pub fn oneline(&self) -> String {
+		format!(
+			"{}: {} | {} | {} | '{}'",
+			self.id,
+			self.primary_text,
+			self.analyzed_words.join(" "),
+			self.glosses.join(" "),
+			self.translated_text
+		)
 	}
 }
 
-#[derive(Debug)]
+// columns other than id and name are optional so that LanguageTables of
+// datasets other than WALS (Grambank, APiCS, ...) can be read as well
+#[derive(Debug, Clone)]
+pub struct Language {
+	pub id: String,
+	pub name: String,
+	pub macroarea: Option<String>,
+	pub latitude: Option<f32>,
+	pub longitude: Option<f32>,
+	pub glottocode: Option<String>,
+	pub iso6393p3code: Option<String>,
+	pub family: Option<String>,
+	pub subfamily: Option<String>,
+	pub genus: Option<String>,
+	// WALS only
+	pub genus_icon: Option<String>,
+	pub iso_codes: Option<String>,
+	pub samples_100: Option<bool>,
+	pub samples_200: Option<bool>,
+	pub country_id: Option<String>,
+	pub sources: Vec<SourceRef>,
+	pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Chapter {
 	pub id: String,
 	pub name: String,
@@ -114,7 +229,7 @@ pub struct Chapter {
 	pub contributor: String,
 	pub citation: String,
 	pub wp_slug: String,
-	pub number: usize,
+	pub number: Option<usize>,
 	pub area_id: Option<usize>,
 	pub source: String,
 	pub contributor_id: String,
@@ -129,130 +244,167 @@ impl Chapter {
 	}
 }
 
-impl TryFrom<StringRecord> for Chapter {
+impl TableRow for Chapter {
+	const COMPONENT: Component = Component::Contributions;
+}
+
+impl TryFrom<Record<'_>> for Chapter {
 	type Error = String;
-	fn try_from(value: StringRecord) -> Result<Self, Self::Error> {
-		match value.into_iter().collect::<Vec<_>>().as_slice() {
-			&[id, name, description, contributor, citation, wp_slug, number, area_id, source, contributor_id, with_contributor_id] => {
-				match (number.parse::<usize>(), area_id.parse::<usize>()) {
-					(Ok(number), area_id) => Ok(Self {
-						id: id.to_string(),
-						name: name.to_string(),
-						description: description.to_string(),
-						contributor: contributor.to_string(),
-						citation: citation.to_string(),
-						wp_slug: wp_slug.to_string(),
-						number,
-						area_id: area_id.ok(),
-						source: source.to_string(),
-						contributor_id: contributor_id.to_string(),
-						with_contributor_id: with_contributor_id.to_string(),
-					}),
-					(Err(e), _) => Err(e.to_string()),
-				}
-			}
-			x => Err(format!("{:?} does not match column number", x)),
-		}
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		let number = r
+			.get("Number")
+			.map(|n| n.parse::<usize>())
+			.transpose()
+			.map_err(|e| e.to_string())?;
+
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			name: r.get_or_empty("name"),
+			description: r.get_or_empty("description"),
+			contributor: r.get_or_empty("contributor"),
+			citation: r.get_or_empty("citation"),
+			wp_slug: r.get_or_empty("wp_slug"),
+			number,
+			area_id: r.get("Area_ID").and_then(|a| a.parse::<usize>().ok()),
+			source: r.get_or_empty("source"),
+			contributor_id: r.get_or_empty("Contributor_ID"),
+			with_contributor_id: r.get_or_empty("With_Contributor_ID"),
+		})
 	}
 }
 
-impl TryFrom<StringRecord> for Language {
+impl TableRow for Language {
+	const COMPONENT: Component = Component::Languages;
+}
+
+impl TryFrom<Record<'_>> for Language {
 	type Error = String;
-	fn try_from(value: StringRecord) -> Result<Self, Self::Error> {
-		match value.into_iter().collect::<Vec<_>>().as_slice() {
-			&[id, name, macroarea, latitude, longitude, glottocode, iso6393p3code, family, subfamily, genus, genus_icon, iso_codes, samples_100, samples_200, country_id, source, parent_id] => {
-				match (
-					latitude.parse::<f32>(),
-					longitude.parse::<f32>(),
-					samples_100.parse::<bool>(),
-					samples_200.parse::<bool>(),
-				) {
-					(Ok(latitude), Ok(longitude), Ok(samples_100), Ok(samples_200)) => Ok(Self {
-						id: id.to_string(),
-						name: name.to_string(),
-						macroarea: macroarea.to_string(),
-						latitude,
-						longitude,
-						glottocode: glottocode.to_string(),
-						iso6393p3code: iso6393p3code.to_string(),
-						family: family.to_string(),
-						subfamily: subfamily.to_string(),
-						genus: genus.to_string(),
-						genus_icon: genus_icon.to_string(),
-						iso_codes: iso_codes.to_string(),
-						samples_100,
-						samples_200,
-						country_id: country_id.to_string(),
-						source: source.to_string(),
-						parent_id: parent_id.to_string(),
-					}),
-					(Err(e), _, _, _) => {
-						println!("{e}");
-						Err(e.to_string())
-					}
-					(Ok(_), Err(e), _, _) => {
-						println!("{e}");
-						Err(e.to_string())
-					}
-					(_, _, Err(e), _) => {
-						println!("{e}");
-						Err(e.to_string())
-					}
-					(_, _, _, Err(e)) => {
-						println!("{e}");
-						Err(e.to_string())
-					}
-				}
-			}
-			x => Err(format!("{:?} does not match column number", x)),
-		}
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		let parse_f32 = |key: &str| {
+			r.get(key)
+				.map(|s| s.parse::<f32>())
+				.transpose()
+				.map_err(|e| format!("{e} @{key}"))
+		};
+		let parse_bool = |key: &str| {
+			r.get(key)
+				.map(|s| s.parse::<bool>())
+				.transpose()
+				.map_err(|e| format!("{e} @{key}"))
+		};
+		let get = |key: &str| r.get(key).map(|s| s.to_string());
+
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			name: r.get_or_empty("name"),
+			macroarea: get("macroarea"),
+			latitude: parse_f32("latitude")?,
+			longitude: parse_f32("longitude")?,
+			glottocode: get("glottocode"),
+			iso6393p3code: get("iso639P3code"),
+			family: get("Family").or_else(|| get("Family_name")),
+			subfamily: get("Subfamily"),
+			genus: get("Genus"),
+			genus_icon: get("GenusIcon"),
+			iso_codes: get("ISO_codes"),
+			samples_100: parse_bool("Samples_100")?,
+			samples_200: parse_bool("Samples_200")?,
+			country_id: get("Country_ID"),
+			sources: r.get_list("Source").iter().map(|s| SourceRef::from(s.as_str())).collect(),
+			parent_id: get("Parent_ID"),
+		})
 	}
 }
 
-impl TryFrom<StringRecord> for Parameter {
+impl TableRow for Parameter {
+	const COMPONENT: Component = Component::Parameters;
+}
+
+impl TryFrom<Record<'_>> for Parameter {
 	type Error = String;
-	fn try_from(value: StringRecord) -> Result<Self, Self::Error> {
-		match value.into_iter().collect::<Vec<_>>().as_slice() {
-			&[id, name, description, column_spec, chapter_id] => usize::from_str_radix(chapter_id, 10)
-				.map(|ch| Self {
-					id: id.to_string(),
-					name: name.to_string(),
-					description: description.to_string(),
-					column_spec: column_spec.to_string(),
-					chapter_id: ch,
-				})
-				.map_err(|e| e.to_string()),
-			x => Err(format!("{:?} does not match column number", x)),
-		}
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		let chapter_id = r
+			.get("Chapter_ID")
+			.map(|c| c.parse::<usize>())
+			.transpose()
+			.map_err(|e| e.to_string())?;
+
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			name: r.get_or_empty("name"),
+			description: r.get_or_empty("description"),
+			column_spec: r.get_or_empty("columnSpec"),
+			chapter_id,
+		})
 	}
 }
 
-impl TryFrom<StringRecord> for Value {
+impl TableRow for Code {
+	const COMPONENT: Component = Component::Codes;
+}
+
+impl TryFrom<Record<'_>> for Code {
 	type Error = String;
-	fn try_from(value: StringRecord) -> Result<Self, Self::Error> {
-		match value.into_iter().collect::<Vec<_>>().as_slice() {
-			&[id, language_id, parameter_id, value, code_id, comment, source, example_id] => {
-				usize::from_str_radix(&value, 10)
-					.map(|value| Self {
-						id: id.to_string(),
-						language_id: language_id.to_string(),
-						parameter_id: parameter_id.to_string(),
-						value,
-						code_id: code_id.to_string(),
-						comment: comment.to_string(),
-						source: source.to_string(),
-						example_id: example_id.to_string(),
-					})
-					.map_err(|e| e.to_string())
-			}
-			x => Err(format!("{:?} does not match column number", x)),
-		}
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		let number = r
+			.get("Number")
+			.map(|n| n.parse::<usize>())
+			.transpose()
+			.map_err(|e| e.to_string())?;
+
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			parameter_id: r.require("parameterReference")?.to_string(),
+			name: r.get_or_empty("name"),
+			description: r.get_or_empty("description"),
+			number,
+		})
+	}
+}
+
+impl TableRow for Example {
+	const COMPONENT: Component = Component::Examples;
+}
+
+impl TryFrom<Record<'_>> for Example {
+	type Error = String;
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			language_id: r.get_or_empty("languageReference"),
+			primary_text: r.get_or_empty("primaryText"),
+			analyzed_words: r.get_list("analyzedWord"),
+			glosses: r.get_list("gloss"),
+			translated_text: r.get_or_empty("translatedText"),
+			meta_language_id: r.get("metaLanguageReference").map(|s| s.to_string()),
+			comment: r.get_or_empty("comment"),
+		})
+	}
+}
+
+impl TableRow for Value {
+	const COMPONENT: Component = Component::Values;
+}
+
+impl TryFrom<Record<'_>> for Value {
+	type Error = String;
+	fn try_from(r: Record) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: r.require("id")?.to_string(),
+			language_id: r.require("languageReference")?.to_string(),
+			parameter_id: r.require("parameterReference")?.to_string(),
+			value: r.get("value").map(|v| v.to_string()),
+			code_id: r.get("codeReference").map(|c| c.to_string()),
+			comment: r.get_or_empty("comment"),
+			sources: r.get_list("source").iter().map(|s| SourceRef::from(s.as_str())).collect(),
+			example_ids: r.get_list("exampleReference"),
+		})
 	}
 }
 
 #[test]
 fn read() {
-	match read_csv::<Chapter, _>("chapters.csv") {
+	match Metadata::find("cldf").and_then(|m| read_table::<Chapter>(&m)) {
 		Ok(rs) => {
 			println!("{:#?}", rs);
 		}
@@ -263,9 +415,53 @@ fn read() {
 	}
 }
 
+#[test]
+fn code_label() {
+	let codes = Codes::new([
+		Code {
+			id: "81A-2".to_string(),
+			parameter_id: "81A".to_string(),
+			name: "SVO".to_string(),
+			description: "Subject-verb-object (SVO)".to_string(),
+			number: Some(2),
+		},
+		Code {
+			id: "GB020-0".to_string(),
+			parameter_id: "GB020".to_string(),
+			name: "0".to_string(),
+			description: "absent".to_string(),
+			number: None,
+		},
+	]);
+
+	assert_eq!(codes.name("81A", "2"), Some("SVO"));
+	assert_eq!(codes.label("81A", "2"), "2 = SVO");
+	assert_eq!(codes.label("GB020", "0"), "0 = absent");
+	assert_eq!(codes.label("81A", "7"), "7");
+}
+
+#[test]
+fn interlinear() {
+	let example = Example {
+		id: "igt-1".to_string(),
+		language_id: "mnd".to_string(),
+		primary_text: "tāmen tōu zìxíngchē".to_string(),
+		analyzed_words: vec!["tāmen".to_string(), "tōu".to_string(), "zìxíngchē".to_string()],
+		glosses: vec!["3PL".to_string(), "steal".to_string(), "bicycle".to_string()],
+		translated_text: "They steal bicycles.".to_string(),
+		meta_language_id: None,
+		comment: String::new(),
+	};
+
+	assert_eq!(
+		example.interlinear(),
+		"tāmen tōu zìxíngchē\ntāmen tōu   zìxíngchē\n3PL   steal bicycle\n'They steal bicycles.'"
+	);
+}
+
 #[test]
 fn read_lang() {
-	match read_csv::<Language, _>("languages.csv") {
+	match Metadata::find("cldf").and_then(|m| read_table::<Language>(&m)) {
 		Ok(rs) => {
 			let fil = rs
 				.into_iter()
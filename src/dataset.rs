@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+use crate::{
+	csvs::{read_optional_table, read_table, Chapter, Codes, Example, Language, Parameter, Value},
+	metadata::Metadata,
+};
+
+// genealogical level languages are grouped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Grouping {
+	Family,
+	Genus,
+}
+
+impl Grouping {
+	pub fn of<'a>(&self, lang: &'a Language) -> Option<&'a String> {
+		match self {
+			Self::Family => lang.family.as_ref(),
+			Self::Genus => lang.genus.as_ref(),
+		}
+	}
+}
+
+// every table of a dataset, parsed once, plus the indexes the subcommands look up by
+#[derive(Debug, Clone)]
+pub struct Dataset {
+	pub metadata: Metadata,
+	pub languages: HashMap<String, Language>,
+	pub parameters: HashMap<String, Parameter>,
+	pub values: Vec<Value>,
+	pub codes: Codes,
+	pub chapters: HashMap<usize, Chapter>,
+	pub examples: HashMap<String, Example>,
+
+	values_by_id: HashMap<String, usize>,
+	values_by_language: HashMap<String, Vec<usize>>,
+	values_by_parameter: HashMap<String, Vec<usize>>,
+	languages_by_value: HashMap<String, HashMap<String, HashSet<String>>>, // <parameter_id, <value, language_ids>>
+	languages_by_family: HashMap<String, Vec<String>>,
+	languages_by_genus: HashMap<String, Vec<String>>,
+	languages_by_macroarea: HashMap<String, Vec<String>>,
+}
+
+impl Dataset {
+	pub fn load(metadata: Metadata) -> Result<Self, String> {
+		let languages = read_table::<Language>(&metadata)?
+			.into_iter()
+			.map(|l| (l.id.clone(), l))
+			.collect();
+
+		let parameters = read_table::<Parameter>(&metadata)?
+			.into_iter()
+			.map(|p| (p.id.clone(), p))
+			.collect();
+
+		let values = read_table::<Value>(&metadata)?;
+		let codes = Codes::read(&metadata)?;
+
+		let chapters = read_optional_table::<Chapter>(&metadata)?
+			.into_iter()
+			.filter_map(|c| c.id.parse::<usize>().map(|id| (id, c)).ok())
+			.collect();
+
+		let examples = read_optional_table::<Example>(&metadata)?
+			.into_iter()
+			.map(|e| (e.id.clone(), e))
+			.collect();
+
+		Ok(Self::new(metadata, languages, parameters, values, codes, chapters, examples))
+	}
+
+	pub fn new(
+		metadata: Metadata,
+		languages: HashMap<String, Language>,
+		parameters: HashMap<String, Parameter>,
+		values: Vec<Value>,
+		codes: Codes,
+		chapters: HashMap<usize, Chapter>,
+		examples: HashMap<String, Example>,
+	) -> Self {
+		let mut values_by_id: HashMap<String, usize> = HashMap::new();
+		let mut values_by_language: HashMap<String, Vec<usize>> = HashMap::new();
+		let mut values_by_parameter: HashMap<String, Vec<usize>> = HashMap::new();
+		let mut languages_by_value: HashMap<String, HashMap<String, HashSet<String>>> = HashMap::new();
+
+		for (i, value) in values.iter().enumerate() {
+			values_by_id.insert(value.id.clone(), i);
+			values_by_language
+				.entry(value.language_id.clone())
+				.or_default()
+				.push(i);
+			values_by_parameter
+				.entry(value.parameter_id.clone())
+				.or_default()
+				.push(i);
+
+			if let Some(v) = &value.value {
+				languages_by_value
+					.entry(value.parameter_id.clone())
+					.or_default()
+					.entry(v.clone())
+					.or_default()
+					.insert(value.language_id.clone());
+			}
+		}
+
+		let group = |key: fn(&Language) -> Option<&String>| {
+			let mut h: HashMap<String, Vec<String>> = HashMap::new();
+			for lang in languages.values() {
+				if let Some(k) = key(lang) {
+					h.entry(k.clone()).or_default().push(lang.id.clone());
+				}
+			}
+			h.values_mut().for_each(|ids| ids.sort());
+			h
+		};
+
+		let languages_by_family = group(|l| Grouping::Family.of(l));
+		let languages_by_genus = group(|l| Grouping::Genus.of(l));
+		let languages_by_macroarea = group(|l| l.macroarea.as_ref());
+
+		Self {
+			metadata,
+			languages,
+			parameters,
+			values,
+			codes,
+			chapters,
+			examples,
+			values_by_id,
+			values_by_language,
+			values_by_parameter,
+			languages_by_value,
+			languages_by_family,
+			languages_by_genus,
+			languages_by_macroarea,
+		}
+	}
+
+	// the same dataset with only these languages and their values
+	pub fn select(self, lang_ids: &HashSet<String>) -> Self {
+		let languages = self
+			.languages
+			.into_iter()
+			.filter(|(id, _)| lang_ids.contains(id))
+			.collect();
+		let values = self
+			.values
+			.into_iter()
+			.filter(|v| lang_ids.contains(&v.language_id))
+			.collect();
+
+		Self::new(
+			self.metadata,
+			languages,
+			self.parameters,
+			values,
+			self.codes,
+			self.chapters,
+			self.examples,
+		)
+	}
+
+	pub fn language(&self, lang_id: &str) -> Option<&Language> {
+		self.languages.get(lang_id)
+	}
+
+	pub fn value(&self, value_id: &str) -> Option<&Value> {
+		self.values_by_id.get(value_id).map(|i| &self.values[*i])
+	}
+
+	pub fn values_of_language(&self, lang_id: &str) -> Vec<&Value> {
+		self.indexed_values(self.values_by_language.get(lang_id))
+	}
+
+	pub fn values_of_parameter(&self, parameter_id: &str) -> Vec<&Value> {
+		self.indexed_values(self.values_by_parameter.get(parameter_id))
+	}
+
+	// <value, language_ids> of one parameter
+	pub fn value_languages(&self, parameter_id: &str) -> Option<&HashMap<String, HashSet<String>>> {
+		self.languages_by_value.get(parameter_id)
+	}
+
+	// the values of a parameter in code order, values without a code after them
+	pub fn parameter_values(&self, parameter_id: &str) -> Vec<String> {
+		let mut values = self
+			.codes
+			.of_parameter(parameter_id)
+			.iter()
+			.map(|c| c.value())
+			.collect::<Vec<_>>();
+		let mut uncoded = self
+			.value_languages(parameter_id)
+			.map(|vl| vl.keys().filter(|v| !values.contains(v)).cloned().collect::<Vec<_>>())
+			.unwrap_or_default();
+		uncoded.sort();
+		values.extend(uncoded);
+		values
+	}
+
+	pub fn languages_with_value(&self, parameter_id: &str, value: &str) -> Option<&HashSet<String>> {
+		self.languages_by_value.get(parameter_id)?.get(value)
+	}
+
+	pub fn languages_of_family(&self, family: &str) -> &[String] {
+		self.languages_by_family.get(family).map(|v| v.as_slice()).unwrap_or_default()
+	}
+
+	pub fn languages_of_genus(&self, genus: &str) -> &[String] {
+		self.languages_by_genus.get(genus).map(|v| v.as_slice()).unwrap_or_default()
+	}
+
+	pub fn languages_of_group(&self, grouping: Grouping, group: &str) -> &[String] {
+		match grouping {
+			Grouping::Family => self.languages_of_family(group),
+			Grouping::Genus => self.languages_of_genus(group),
+		}
+	}
+
+	pub fn groups(&self, grouping: Grouping) -> Vec<&String> {
+		match grouping {
+			Grouping::Family => self.families(),
+			Grouping::Genus => self.genera(),
+		}
+	}
+
+	pub fn languages_of_macroarea(&self, macroarea: &str) -> &[String] {
+		self.languages_by_macroarea.get(macroarea).map(|v| v.as_slice()).unwrap_or_default()
+	}
+
+	pub fn families(&self) -> Vec<&String> {
+		let mut v = self.languages_by_family.keys().collect::<Vec<_>>();
+		v.sort();
+		v
+	}
+
+	pub fn genera(&self) -> Vec<&String> {
+		let mut v = self.languages_by_genus.keys().collect::<Vec<_>>();
+		v.sort();
+		v
+	}
+
+	pub fn macroareas(&self) -> Vec<&String> {
+		let mut v = self.languages_by_macroarea.keys().collect::<Vec<_>>();
+		v.sort();
+		v
+	}
+
+	fn indexed_values(&self, indices: Option<&Vec<usize>>) -> Vec<&Value> {
+		indices
+			.map(|is| is.iter().map(|i| &self.values[*i]).collect())
+			.unwrap_or_default()
+	}
+}
+
+// a handful of made up languages for the tests of the modules built on Dataset
+#[cfg(test)]
+pub fn toy() -> Dataset {
+	let lang = |id: &str, family: &str, genus: &str, macroarea: &str, lat: f32, lon: f32| Language {
+		id: id.to_string(),
+		name: id.to_uppercase(),
+		macroarea: Some(macroarea.to_string()),
+		latitude: Some(lat),
+		longitude: Some(lon),
+		glottocode: None,
+		iso6393p3code: None,
+		family: Some(family.to_string()),
+		subfamily: None,
+		genus: Some(genus.to_string()),
+		genus_icon: None,
+		iso_codes: None,
+		samples_100: Some(id < "c"),
+		samples_200: Some(true),
+		country_id: None,
+		sources: vec![],
+		parent_id: None,
+	};
+
+	let languages = [
+		lang("aaa", "F1", "G1", "Eurasia", 50.0, 10.0),
+		lang("aab", "F1", "G1", "Eurasia", 51.0, 11.0),
+		lang("bba", "F1", "G2", "Eurasia", 45.0, 20.0),
+		lang("cca", "F2", "G3", "Africa", 0.0, 30.0),
+		lang("ccb", "F2", "G3", "Africa", 1.0, 31.0),
+		lang("dda", "F3", "G4", "South America", -10.0, -60.0),
+		// WALS also lists its families and genera as rows without values
+		Language {
+			id: "family-f1".to_string(),
+			name: "F1".to_string(),
+			macroarea: None,
+			latitude: None,
+			longitude: None,
+			family: None,
+			genus: None,
+			samples_100: None,
+			samples_200: None,
+			..lang("family-f1", "", "", "", 0.0, 0.0)
+		},
+	]
+	.into_iter()
+	.map(|l| (l.id.clone(), l))
+	.collect();
+
+	let parameters = ["P1", "P2", "P3"]
+		.into_iter()
+		.map(|id| {
+			(
+				id.to_string(),
+				Parameter {
+					id: id.to_string(),
+					name: format!("Parameter {id}"),
+					description: String::new(),
+					column_spec: String::new(),
+					chapter_id: None,
+				},
+			)
+		})
+		.collect();
+
+	let table = [
+		("aaa", "P1", "1"),
+		("aab", "P1", "1"),
+		("bba", "P1", "1"),
+		("cca", "P1", "2"),
+		("ccb", "P1", "2"),
+		("dda", "P1", "3"),
+		("aaa", "P2", "1"),
+		("aab", "P2", "1"),
+		("bba", "P2", "2"),
+		("cca", "P2", "2"),
+		("ccb", "P2", "2"),
+		("aaa", "P3", "1"),
+		("bba", "P3", "1"),
+		("cca", "P3", "2"),
+		("dda", "P3", "2"),
+	];
+
+	let values = table
+		.into_iter()
+		.map(|(lang_id, param_id, value)| Value {
+			id: format!("{param_id}-{lang_id}"),
+			language_id: lang_id.to_string(),
+			parameter_id: param_id.to_string(),
+			value: Some(value.to_string()),
+			code_id: None,
+			comment: String::new(),
+			sources: vec![],
+			example_ids: vec![],
+		})
+		.collect();
+
+	Dataset::new(
+		Metadata::default(),
+		languages,
+		parameters,
+		values,
+		Codes::default(),
+		HashMap::new(),
+		HashMap::new(),
+	)
+}
+
+#[test]
+fn indexes() {
+	let dataset = toy();
+
+	assert_eq!(dataset.values_of_language("aaa").len(), 3);
+	assert_eq!(dataset.value("P2-bba").and_then(|v| v.value.as_deref()), Some("2"));
+	assert!(dataset.value("P2-dda").is_none());
+	assert_eq!(dataset.values_of_parameter("P2").len(), 5);
+	assert_eq!(
+		dataset.languages_with_value("P1", "2"),
+		Some(&HashSet::from(["cca".to_string(), "ccb".to_string()]))
+	);
+	assert_eq!(dataset.languages_of_family("F1"), ["aaa", "aab", "bba"]);
+	assert_eq!(dataset.languages_of_genus("G3"), ["cca", "ccb"]);
+	assert_eq!(dataset.macroareas(), ["Africa", "Eurasia", "South America"]);
+}
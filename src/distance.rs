@@ -0,0 +1,599 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{self, File},
+	path::{Path, PathBuf},
+};
+
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+
+use crate::{
+	collect::{collect_values_per_language, ParamValueMap},
+	csvs::Language,
+	dataset::Dataset,
+	filter::LanguageFilter,
+	nexus::{nexus_label, nexus_taxa_block},
+	util::ensure_dir,
+};
+
+const DISTANCE_FILE: &str = "out/distance";
+// so that a language agreeing on a single parameter does not top the ranking
+const SIMILAR_MIN_OVERLAP: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+	/// Share of the shared parameters with different values
+	Hamming,
+	/// Like hamming, but the values of --ordinal parameters differ by their distance over the
+	/// parameter's range
+	Gower,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MatrixFormat {
+	Csv,
+	Phylip,
+	Nexus,
+}
+
+impl MatrixFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::Csv => "csv",
+			Self::Phylip => "phy",
+			Self::Nexus => "nex",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DistanceOptions {
+	#[arg(long, value_enum, default_value_t = Metric::Hamming)]
+	pub metric: Metric,
+
+	/// Leave the distance of two languages missing when they share fewer parameters;
+	/// 1 by default, for similar 10 or the number of --params
+	#[arg(long)]
+	pub min_overlap: Option<usize>,
+
+	/// Csv of parameter_id,weight; unlisted parameters weigh 1
+	#[arg(long)]
+	pub weights: Option<PathBuf>,
+
+	/// Parameters whose values are ordered numbers, e.g. 1A,2A; gower scales only these by
+	/// their range, all other values just differ or not, numeric codes included
+	#[arg(long, value_delimiter = ',')]
+	pub ordinal: Vec<String>,
+}
+
+/// Write the pairwise distance matrix of languages over the parameters they share
+#[derive(Debug, Args)]
+pub struct Distance {
+	/// Languages to compare, all languages with values when omitted
+	lang_ids: Vec<String>,
+
+	#[command(flatten)]
+	options: DistanceOptions,
+
+	#[arg(long, value_enum, default_value_t = MatrixFormat::Csv)]
+	format: MatrixFormat,
+
+	/// Leave out the languages with the most missing distances until none is missing,
+	/// which phylip and nexus require
+	#[arg(long)]
+	drop_incomplete: bool,
+}
+
+/// Rank the other languages by how many of the parameters they share with a language agree;
+/// the filters, e.g. --macroarea or --family, choose the languages compared with
+#[derive(Debug, Args)]
+pub struct Similar {
+	lang_id: String,
+
+	/// Only compare these parameters, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	#[command(flatten)]
+	options: DistanceOptions,
+
+	/// Number of languages printed
+	#[arg(long, default_value_t = 20)]
+	top: usize,
+}
+
+impl Distance {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let comparer = Comparer::new(dataset, &self.options)?;
+		let lang_ids = match self.lang_ids.is_empty() {
+			true => {
+				let mut ids = comparer.values.keys().cloned().collect::<Vec<_>>();
+				ids.sort();
+				ids
+			}
+			false => self.lang_ids,
+		};
+
+		let mut matrix = comparer.matrix(&lang_ids)?;
+		if self.drop_incomplete {
+			print_dropped(&matrix.drop_incomplete());
+		}
+		let text = match self.format {
+			MatrixFormat::Csv => matrix.to_csv()?,
+			MatrixFormat::Phylip => matrix.to_phylip()?,
+			MatrixFormat::Nexus => matrix.to_nexus()?,
+		};
+
+		let path = out_dir.join(format!("{DISTANCE_FILE}.{}", self.format.extension()));
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+		fs::write(&path, text).map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!(
+			"{} languages, {} pairs below {} shared parameters, written to {}",
+			matrix.lang_ids.len(),
+			matrix.missing_pairs(),
+			comparer.min_overlap,
+			path.display()
+		);
+
+		Ok(())
+	}
+}
+
+pub fn print_dropped(dropped: &[String]) {
+	if !dropped.is_empty() {
+		let ids = dropped.iter().map(|id| id.as_str()).intersperse(", ").collect::<String>();
+		println!("{} languages dropped for missing distances: {ids}", dropped.len());
+	}
+}
+
+impl Similar {
+	// the dataset is not filtered, so that the language itself is there
+	pub fn print(self, dataset: &Dataset, filter: &LanguageFilter) -> Result<(), String> {
+		let lang = dataset
+			.language(&self.lang_id)
+			.ok_or_else(|| format!("language {} not found", self.lang_id))?;
+
+		let candidates = filter.selected(dataset)?;
+		let comparer = self.comparer(dataset)?;
+		let ranked = self.rank(dataset, &comparer, candidates.as_ref());
+		println!("{} {} ({} candidates)", lang.id, lang.name, ranked.len());
+		for (i, (other, agreement, compared)) in ranked.into_iter().take(self.top).enumerate() {
+			println!(
+				"{:>4} {:<8} {:<30} {:<20} {:.4} {:>4}",
+				i + 1,
+				other.id,
+				other.name,
+				other.family.as_deref().unwrap_or_default(),
+				agreement,
+				compared
+			);
+		}
+
+		Ok(())
+	}
+
+	fn comparer(&self, dataset: &Dataset) -> Result<Comparer, String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let min_overlap = match self.params.is_empty() {
+			true => SIMILAR_MIN_OVERLAP,
+			false => SIMILAR_MIN_OVERLAP.min(self.params.len()),
+		};
+		let options = DistanceOptions {
+			min_overlap: Some(self.options.min_overlap.unwrap_or(min_overlap)),
+			..self.options.clone()
+		};
+
+		let mut comparer = Comparer::new(dataset, &options)?;
+		if !self.params.is_empty() {
+			comparer.restrict(self.params.iter().cloned().collect());
+		}
+		Ok(comparer)
+	}
+
+	// (language, agreement rate, compared parameters), most similar first
+	fn rank<'a>(
+		&self,
+		dataset: &'a Dataset,
+		comparer: &Comparer,
+		candidates: Option<&HashSet<String>>,
+	) -> Vec<(&'a Language, f32, usize)> {
+		let mut ranked = dataset
+			.languages
+			.values()
+			.filter(|l| l.id != self.lang_id)
+			.filter(|l| candidates.is_none_or(|ids| ids.contains(&l.id)))
+			.filter_map(|l| {
+				let (d, compared) = comparer.compare(&self.lang_id, &l.id)?;
+				Some((l, 1.0 - d, compared))
+			})
+			.collect::<Vec<_>>();
+
+		ranked.sort_by(|a, b| {
+			b.1.total_cmp(&a.1)
+				.then_with(|| b.2.cmp(&a.2))
+				.then_with(|| a.0.id.cmp(&b.0.id))
+		});
+
+		ranked
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct WeightRow {
+	parameter_id: String,
+	weight: f32,
+}
+
+// the values of every language plus what is needed to compare two of them
+#[derive(Debug, Clone)]
+pub struct Comparer {
+	metric: Metric,
+	min_overlap: usize,
+	weights: HashMap<String, f32>,
+	parameters: Option<HashSet<String>>, // only these are compared when given
+	ranges: HashMap<String, (f32, f32)>, // <parameter_id, (min, max)> of ordinal parameters
+	values: HashMap<String, ParamValueMap>,
+}
+
+impl Comparer {
+	pub fn new(dataset: &Dataset, options: &DistanceOptions) -> Result<Self, String> {
+		let weights = match &options.weights {
+			Some(path) => read_weights(path)?,
+			None => HashMap::new(),
+		};
+
+		let mut ranges = HashMap::new();
+		for param_id in options.ordinal.iter() {
+			if !dataset.parameters.contains_key(param_id) {
+				return Err(format!("parameter {param_id} not found"));
+			}
+			let numbers = dataset
+				.value_languages(param_id)
+				.map(|vl| vl.keys().map(|v| v.parse::<f32>().ok()).collect::<Option<Vec<_>>>())
+				.unwrap_or(Some(vec![]))
+				.ok_or_else(|| format!("parameter {param_id} of --ordinal has values that are not numbers"))?;
+			if let (Some(min), Some(max)) = (
+				numbers.iter().copied().reduce(f32::min),
+				numbers.iter().copied().reduce(f32::max),
+			) {
+				ranges.insert(param_id.clone(), (min, max));
+			}
+		}
+
+		Ok(Self {
+			metric: options.metric,
+			min_overlap: options.min_overlap.unwrap_or(1),
+			weights,
+			parameters: None,
+			ranges,
+			values: collect_values_per_language(dataset),
+		})
+	}
+
+	// compare other values than the dataset's, e.g. with some cells held out
+	pub fn with_values(self, values: HashMap<String, ParamValueMap>) -> Self {
+		Self { values, ..self }
+	}
+
+	// compare only these parameters from now on
+	pub fn restrict(&mut self, param_ids: HashSet<String>) {
+		self.parameters = Some(param_ids);
+	}
+
+	pub fn weight(&self, param_id: &str) -> f32 {
+		if let Some(parameters) = &self.parameters
+			&& !parameters.contains(param_id)
+		{
+			return 0.0;
+		}
+
+		self.weights.get(param_id).copied().unwrap_or(1.0)
+	}
+
+	pub fn values(&self, lang_id: &str) -> Option<&ParamValueMap> {
+		self.values.get(lang_id)
+	}
+
+	// difference of two values of one parameter, from 0 to 1
+	fn difference(&self, param_id: &str, a: &str, b: &str) -> f32 {
+		if a == b {
+			return 0.0;
+		}
+
+		match (self.metric, self.ranges.get(param_id)) {
+			(Metric::Gower, Some((min, max))) if max > min => {
+				match (a.parse::<f32>(), b.parse::<f32>()) {
+					(Ok(a), Ok(b)) => (a - b).abs() / (max - min),
+					_ => 1.0,
+				}
+			}
+			_ => 1.0,
+		}
+	}
+
+	// weighted distance and number of shared parameters, None when they share
+	// fewer than min_overlap
+	pub fn compare(&self, lang_a: &str, lang_b: &str) -> Option<(f32, usize)> {
+		let a = self.values.get(lang_a)?;
+		let b = self.values.get(lang_b)?;
+		let (a, b) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+		let (mut sum, mut total, mut shared) = (0.0, 0.0, 0);
+		for (param_id, value_a) in a {
+			let Some(value_b) = b.get(param_id) else {
+				continue;
+			};
+			let w = self.weight(param_id);
+			if w <= 0.0 {
+				continue;
+			}
+
+			sum += w * self.difference(param_id, value_a, value_b);
+			total += w;
+			shared += 1;
+		}
+
+		match shared >= self.min_overlap.max(1) {
+			true => Some((sum / total, shared)),
+			false => None,
+		}
+	}
+
+	pub fn matrix(&self, lang_ids: &[String]) -> Result<DistanceMatrix, String> {
+		if let Some(id) = lang_ids.iter().find(|id| !self.values.contains_key(*id)) {
+			return Err(format!("language {id} not found or without values"));
+		}
+
+		let n = lang_ids.len();
+		let mut distances = vec![vec![Some(0.0); n]; n];
+		for i in 0..n {
+			for j in i + 1..n {
+				let d = self.compare(&lang_ids[i], &lang_ids[j]).map(|(d, _)| d);
+				distances[i][j] = d;
+				distances[j][i] = d;
+			}
+		}
+
+		Ok(DistanceMatrix {
+			lang_ids: lang_ids.to_vec(),
+			distances,
+		})
+	}
+}
+
+fn read_weights(path: &Path) -> Result<HashMap<String, f32>, String> {
+	let f = File::open(path).map_err(|e| format!("{e} @{}", path.display()))?;
+
+	csv::Reader::from_reader(f)
+		.deserialize::<WeightRow>()
+		.map(|row| {
+			row.map(|r| (r.parameter_id, r.weight))
+				.map_err(|e| format!("{e} @{}", path.display()))
+		})
+		.collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct DistanceMatrix {
+	pub lang_ids: Vec<String>,
+	pub distances: Vec<Vec<Option<f32>>>,
+}
+
+impl DistanceMatrix {
+	pub fn get(&self, i: usize, j: usize) -> Option<f32> {
+		self.distances[i][j]
+	}
+
+	pub fn missing_pairs(&self) -> usize {
+		self.distances.iter().flatten().filter(|d| d.is_none()).count() / 2
+	}
+
+	// an error naming the first missing pairs, for formats without missing distances
+	pub fn check_complete(&self, format: &str) -> Result<(), String> {
+		let pairs = (0..self.lang_ids.len())
+			.flat_map(|i| (i + 1..self.lang_ids.len()).map(move |j| (i, j)))
+			.filter(|(i, j)| self.distances[*i][*j].is_none())
+			.map(|(i, j)| format!("{}-{}", self.lang_ids[i], self.lang_ids[j]))
+			.collect::<Vec<_>>();
+		if pairs.is_empty() {
+			return Ok(());
+		}
+
+		let shown = pairs.iter().take(10).map(|p| p.as_str()).intersperse(", ").collect::<String>();
+		Err(format!(
+			"{} pairs share too few parameters ({shown}{}); {format} has no missing distances, lower --min-overlap, pass --drop-incomplete or pick other languages",
+			pairs.len(),
+			if pairs.len() > 10 { ", ..." } else { "" }
+		))
+	}
+
+	// leave out the language with the most missing distances, ties by id, until none
+	// is missing; returns the dropped ids
+	pub fn drop_incomplete(&mut self) -> Vec<String> {
+		let mut dropped = vec![];
+		loop {
+			let worst = (0..self.lang_ids.len())
+				.map(|i| (i, self.distances[i].iter().filter(|d| d.is_none()).count()))
+				.filter(|(_, n)| *n > 0)
+				.max_by(|a, b| a.1.cmp(&b.1).then_with(|| self.lang_ids[b.0].cmp(&self.lang_ids[a.0])));
+			let Some((i, _)) = worst else {
+				return dropped;
+			};
+
+			dropped.push(self.lang_ids.remove(i));
+			self.distances.remove(i);
+			self.distances.iter_mut().for_each(|row| {
+				row.remove(i);
+			});
+		}
+	}
+
+	fn row(&self, i: usize, missing: &str) -> String {
+		self.distances[i]
+			.iter()
+			.map(|d| d.map(|d| format!("{d:.6}")).unwrap_or_else(|| missing.to_string()))
+			.intersperse(" ".to_string())
+			.collect()
+	}
+
+	pub fn to_csv(&self) -> Result<String, String> {
+		let mut w = csv::Writer::from_writer(vec![]);
+		let header = std::iter::once("language_id").chain(self.lang_ids.iter().map(|id| id.as_str()));
+		w.write_record(header).map_err(|e| format!("{e} @to_csv"))?;
+		for (i, id) in self.lang_ids.iter().enumerate() {
+			let cells = self.distances[i].iter().map(|d| d.map(|d| format!("{d:.6}")).unwrap_or_default());
+			w.write_record(std::iter::once(id.clone()).chain(cells))
+				.map_err(|e| format!("{e} @to_csv/{id}"))?;
+		}
+
+		let bytes = w.into_inner().map_err(|e| format!("{e} @to_csv"))?;
+		String::from_utf8(bytes).map_err(|e| format!("{e} @to_csv"))
+	}
+
+	// square matrix with names cut and padded to ten characters, without missing distances
+	pub fn to_phylip(&self) -> Result<String, String> {
+		self.check_complete("phylip")?;
+
+		let names = self
+			.lang_ids
+			.iter()
+			.map(|id| id.chars().take(10).collect::<String>())
+			.collect::<Vec<_>>();
+		let mut seen = HashSet::new();
+		if let Some(i) = names.iter().position(|name| !seen.insert(name)) {
+			return Err(format!(
+				"language {} is {} in its first ten characters like another; phylip names are at most ten",
+				self.lang_ids[i], names[i]
+			));
+		}
+
+		let rows = names
+			.iter()
+			.enumerate()
+			.map(|(i, name)| format!("{name:<10} {}", self.row(i, "")))
+			.intersperse("\n".to_string())
+			.collect::<String>();
+
+		Ok(format!("{}\n{rows}\n", self.lang_ids.len()))
+	}
+
+	pub fn to_nexus(&self) -> Result<String, String> {
+		Ok(format!("#NEXUS\n\n{}\n{}", nexus_taxa_block(&self.lang_ids), self.nexus_distances_block()?))
+	}
+
+	// without missing distances, which SplitsTree does not accept
+	pub fn nexus_distances_block(&self) -> Result<String, String> {
+		self.check_complete("nexus")?;
+
+		let rows = self
+			.lang_ids
+			.iter()
+			.enumerate()
+			.map(|(i, id)| format!("\t\t{} {}\n", nexus_label(id), self.row(i, "")))
+			.collect::<String>();
+
+		Ok(format!(
+			"BEGIN DISTANCES;\n\tDIMENSIONS NTAX={};\n\tFORMAT TRIANGLE=BOTH DIAGONAL LABELS;\n\tMATRIX\n{rows}\t;\nEND;\n",
+			self.lang_ids.len()
+		))
+	}
+}
+
+#[test]
+fn toy_distances() {
+	let options = DistanceOptions {
+		metric: Metric::Hamming,
+		min_overlap: Some(2),
+		weights: None,
+		ordinal: vec![],
+	};
+	let comparer = Comparer::new(&crate::dataset::toy(), &options).unwrap();
+
+	assert_eq!(comparer.compare("aaa", "aab"), Some((0.0, 2)));
+	assert_eq!(comparer.compare("aaa", "bba"), Some((1.0 / 3.0, 3)));
+	assert_eq!(comparer.compare("aab", "dda"), None);
+
+	// numeric codes are nominal unless listed as ordinal
+	let gower = DistanceOptions {
+		metric: Metric::Gower,
+		..options.clone()
+	};
+	let nominal = Comparer::new(&crate::dataset::toy(), &gower).unwrap();
+	assert_eq!(nominal.compare("bba", "cca"), comparer.compare("bba", "cca"));
+	let ordinal = DistanceOptions {
+		ordinal: vec!["P1".to_string()],
+		..gower
+	};
+	let ordinal = Comparer::new(&crate::dataset::toy(), &ordinal).unwrap();
+	assert_eq!(ordinal.compare("bba", "cca"), Some((0.5, 3)));
+	assert_eq!(ordinal.compare("aaa", "bba"), comparer.compare("aaa", "bba"));
+
+	let ids = ["aaa", "bba", "dda"].map(String::from);
+	let matrix = comparer.matrix(&ids).unwrap();
+	assert_eq!(matrix.get(0, 2), matrix.get(2, 0));
+	assert!(matrix.to_nexus().unwrap().contains("MATRIX\n\t\taaa 0.000000 0.333333 1.000000\n"));
+
+	// aab shares only P1 with dda
+	let ids = ["aaa", "aab", "bba", "dda"].map(String::from);
+	let mut incomplete = comparer.matrix(&ids).unwrap();
+	assert!(incomplete.to_nexus().unwrap_err().contains("1 pairs share too few parameters (aab-dda)"));
+	assert_eq!(incomplete.drop_incomplete(), ["aab"]);
+	assert_eq!(incomplete.lang_ids, ["aaa", "bba", "dda"]);
+	assert_eq!(incomplete.distances, matrix.distances);
+
+	let labelled = DistanceMatrix {
+		lang_ids: vec!["a,b".to_string(), "abcdefghijkl".to_string()],
+		distances: vec![vec![Some(0.0), Some(0.5)], vec![Some(0.5), Some(0.0)]],
+	};
+	assert!(labelled.to_csv().unwrap().starts_with("language_id,\"a,b\",abcdefghijkl\n\"a,b\",0.000000,0.500000\n"));
+	assert!(labelled.to_phylip().unwrap().contains("\nabcdefghij 0.500000 0.000000\n"));
+	let clashing = DistanceMatrix {
+		lang_ids: vec!["abcdefghij1".to_string(), "abcdefghij2".to_string()],
+		..labelled
+	};
+	assert!(clashing.to_phylip().is_err());
+}
+
+#[test]
+fn similar_toy() {
+	let dataset = crate::dataset::toy();
+	let similar = Similar {
+		lang_id: "aaa".to_string(),
+		params: vec![],
+		options: DistanceOptions {
+			metric: Metric::Hamming,
+			min_overlap: Some(1),
+			weights: None,
+			ordinal: vec![],
+		},
+		top: 0,
+	};
+
+	let comparer = similar.comparer(&dataset).unwrap();
+	let ranked = similar.rank(&dataset, &comparer, None);
+	assert_eq!(ranked.len(), 5);
+	assert_eq!((ranked[0].0.id.as_str(), ranked[0].1, ranked[0].2), ("aab", 1.0, 2));
+
+	let africa = dataset.languages_of_macroarea("Africa").iter().cloned().collect::<HashSet<_>>();
+	let ranked = similar.rank(&dataset, &comparer, Some(&africa));
+	assert!(ranked.len() == 2 && ranked.iter().all(|(l, _, _)| l.id.starts_with("cc")));
+
+	// by default all three parameters must be compared, which leaves out aab
+	let similar = Similar {
+		params: vec!["P1".to_string(), "P2".to_string(), "P3".to_string()],
+		options: DistanceOptions {
+			min_overlap: None,
+			..similar.options
+		},
+		..similar
+	};
+	let comparer = similar.comparer(&dataset).unwrap();
+	let ranked = similar.rank(&dataset, &comparer, None).into_iter().map(|(l, _, _)| l.id.as_str()).collect::<Vec<_>>();
+	assert_eq!(ranked, ["bba", "cca"]);
+}
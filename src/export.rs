@@ -0,0 +1,172 @@
+use std::{fs, path::Path};
+
+use clap::Args;
+use serde_json::{json, Map, Value};
+
+use crate::{
+	collect::{collect_values_per_language, ParamValueMap},
+	csvs::Language,
+	dataset::Dataset,
+	geo::coordinates,
+	util::ensure_dir,
+};
+
+const GEOJSON_FILE: &str = "out/languages.geojson";
+const KML_FILE: &str = "out/languages.kml";
+
+/// Export the languages with coordinates as GeoJSON and KML, e.g. for QGIS
+#[derive(Debug, Args)]
+pub struct GeoExport {
+	/// Add the values of these parameters as properties, e.g. 81A,82A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+}
+
+impl GeoExport {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let values = collect_values_per_language(dataset);
+		let mut langs = dataset
+			.languages
+			.values()
+			.filter(|l| coordinates(l).is_some())
+			.collect::<Vec<_>>();
+		langs.sort_by(|a, b| a.id.cmp(&b.id));
+
+		let features = langs
+			.iter()
+			.map(|lang| Feature::new(dataset, lang, values.get(&lang.id), &self.params))
+			.collect::<Vec<_>>();
+
+		let geojson = json!({
+			"type": "FeatureCollection",
+			"features": features.iter().map(|f| f.to_geojson()).collect::<Vec<_>>(),
+		});
+		let kml = format!(
+			"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n<name>{}</name>\n{}</Document>\n</kml>\n",
+			xml_escape(dataset.metadata.title.as_deref().unwrap_or("languages")),
+			features.iter().map(|f| f.to_kml()).collect::<String>()
+		);
+
+		for (file, text) in [(GEOJSON_FILE, geojson.to_string()), (KML_FILE, kml)] {
+			let path = out_dir.join(file);
+			if let Some(dir) = path.parent() {
+				ensure_dir(dir)?;
+			}
+			fs::write(&path, text).map_err(|e| format!("{e} @{}", path.display()))?;
+		}
+
+		println!(
+			"{} of {} languages with coordinates written to {} and {}",
+			features.len(),
+			dataset.languages.len(),
+			out_dir.join(GEOJSON_FILE).display(),
+			out_dir.join(KML_FILE).display()
+		);
+
+		Ok(())
+	}
+}
+
+// one located language with its properties in output order
+pub struct Feature {
+	lat: f64,
+	lon: f64,
+	properties: Vec<(String, Option<String>)>,
+}
+
+impl Feature {
+	pub fn new(dataset: &Dataset, lang: &Language, values: Option<&ParamValueMap>, param_ids: &[String]) -> Self {
+		let (lat, lon) = coordinates(lang).unwrap_or_default();
+
+		let mut properties = vec![
+			("id".to_string(), Some(lang.id.clone())),
+			("name".to_string(), Some(lang.name.clone())),
+			("family".to_string(), lang.family.clone()),
+			("genus".to_string(), lang.genus.clone()),
+			("macroarea".to_string(), lang.macroarea.clone()),
+			("glottocode".to_string(), lang.glottocode.clone()),
+		];
+		for param_id in param_ids {
+			let value = values.and_then(|pv| pv.get(param_id));
+			properties.push((param_id.clone(), value.cloned()));
+			properties.push((
+				format!("{param_id}_label"),
+				value.map(|v| dataset.codes.name(param_id, v).unwrap_or(v).to_string()),
+			));
+		}
+
+		Self { lat, lon, properties }
+	}
+
+	pub fn to_geojson(&self) -> Value {
+		let properties = self
+			.properties
+			.iter()
+			.map(|(k, v)| (k.clone(), v.clone().map(Value::String).unwrap_or(Value::Null)))
+			.collect::<Map<_, _>>();
+
+		json!({
+			"type": "Feature",
+			"geometry": { "type": "Point", "coordinates": [self.lon, self.lat] },
+			"properties": properties,
+		})
+	}
+
+	pub fn to_kml(&self) -> String {
+		let name = self
+			.properties
+			.iter()
+			.find(|(k, _)| k == "name")
+			.and_then(|(_, v)| v.as_deref())
+			.unwrap_or_default();
+		let data = self
+			.properties
+			.iter()
+			.filter_map(|(k, v)| {
+				Some(format!(
+					"\t\t<Data name=\"{}\"><value>{}</value></Data>\n",
+					xml_escape(k),
+					xml_escape(v.as_ref()?)
+				))
+			})
+			.collect::<String>();
+
+		format!(
+			"<Placemark>\n\t<name>{}</name>\n\t<ExtendedData>\n{data}\t</ExtendedData>\n\t<Point><coordinates>{},{}</coordinates></Point>\n</Placemark>\n",
+			xml_escape(name),
+			self.lon,
+			self.lat
+		)
+	}
+}
+
+fn xml_escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+#[test]
+fn feature_toy() {
+	let dataset = crate::dataset::toy();
+	let values = collect_values_per_language(&dataset);
+	let lang = dataset.language("aab").unwrap();
+	let params = ["P1".to_string(), "P3".to_string()];
+
+	let feature = Feature::new(&dataset, lang, values.get("aab"), &params);
+	let geojson = feature.to_geojson();
+	assert_eq!(geojson["properties"]["id"], "aab");
+	assert_eq!(geojson["properties"]["P1"], "1");
+	assert!(geojson["properties"]["P3"].is_null());
+	assert_eq!(geojson["geometry"]["coordinates"][1], lang.latitude.unwrap() as f64);
+
+	let kml = feature.to_kml();
+	assert!(kml.contains("<Data name=\"P1\"><value>1</value></Data>"));
+	assert!(!kml.contains("\"P3\""));
+	assert_eq!(xml_escape("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;");
+}
@@ -0,0 +1,228 @@
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use clap::{Args, ValueEnum};
+
+use crate::{csvs::Language, dataset::Dataset, geo::GeoIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Sample {
+	#[value(name = "100")]
+	Hundred,
+	#[value(name = "200")]
+	TwoHundred,
+}
+
+// restricts every subcommand to a population of languages; the criteria given
+// all have to hold, several values of one criterion are alternatives. similar keeps
+// its own language and filters only the candidates, nexus adds --family and --genus
+// to its --lang-ids
+#[derive(Debug, Clone, Default, Args)]
+pub struct LanguageFilter {
+	/// Only the languages of the WALS 100- or 200-language sample
+	#[arg(long, global = true, value_enum)]
+	sample: Option<Sample>,
+
+	/// Only languages of these families, e.g. Indo-European,Uralic
+	#[arg(long, global = true, value_delimiter = ',')]
+	family: Vec<String>,
+
+	/// Only languages of these genera
+	#[arg(long, global = true, value_delimiter = ',')]
+	genus: Vec<String>,
+
+	/// Only languages of these macroareas
+	#[arg(long, global = true, value_delimiter = ',')]
+	macroarea: Vec<String>,
+
+	/// Only languages spoken in these countries, e.g. JP,KR
+	#[arg(long, global = true, value_delimiter = ',')]
+	country: Vec<String>,
+
+	/// Only the languages listed in this file, one id per line
+	#[arg(long, global = true)]
+	lang_file: Option<PathBuf>,
+
+	/// Only languages within a radius of a point, e.g. 35.7,139.7,500
+	#[arg(long, global = true, value_name = "LAT,LON,KM", value_delimiter = ',', allow_hyphen_values = true)]
+	near: Vec<f64>,
+
+	/// Only languages inside a box, e.g. -10,90,30,150; west > east crosses the antimeridian
+	#[arg(long, global = true, value_name = "SOUTH,WEST,NORTH,EAST", value_delimiter = ',', allow_hyphen_values = true)]
+	bbox: Vec<f64>,
+}
+
+impl LanguageFilter {
+	pub fn is_empty(&self) -> bool {
+		self.sample.is_none()
+			&& self.family.is_empty()
+			&& self.genus.is_empty()
+			&& self.macroarea.is_empty()
+			&& self.country.is_empty()
+			&& self.lang_file.is_none()
+			&& self.near.is_empty()
+			&& self.bbox.is_empty()
+	}
+
+	// the dataset narrowed down to the selected languages and their values
+	pub fn apply(&self, dataset: Dataset) -> Result<Dataset, String> {
+		match self.selected(&dataset)? {
+			Some(selected) => Ok(dataset.select(&selected)),
+			None => Ok(dataset),
+		}
+	}
+
+	// the ids of the languages passing the filter, None when there is no filter
+	pub fn selected(&self, dataset: &Dataset) -> Result<Option<HashSet<String>>, String> {
+		if self.is_empty() {
+			return Ok(None);
+		}
+
+		let listed = match &self.lang_file {
+			Some(path) => Some(read_lang_file(path)?),
+			None => None,
+		};
+
+		let located = self.located(dataset)?;
+
+		let selected = dataset
+			.languages
+			.values()
+			.filter(|l| {
+				self.matches(l)
+					&& listed.as_ref().is_none_or(|ids| ids.contains(&l.id))
+					&& located.as_ref().is_none_or(|ids| ids.contains(&l.id))
+			})
+			.map(|l| l.id.clone())
+			.collect::<HashSet<_>>();
+
+		if selected.is_empty() {
+			return Err("no languages match the filter".to_string());
+		}
+
+		Ok(Some(selected))
+	}
+
+	// the languages of any of --family and --genus, for nexus, which adds them up
+	pub fn group_members(&self, dataset: &Dataset) -> HashSet<String> {
+		let families = self.family.iter().flat_map(|f| dataset.languages_of_family(f));
+		let genera = self.genus.iter().flat_map(|g| dataset.languages_of_genus(g));
+		families.chain(genera).cloned().collect()
+	}
+
+	// the same filter without --family and --genus
+	pub fn without_groups(&self) -> Self {
+		Self {
+			family: vec![],
+			genus: vec![],
+			..self.clone()
+		}
+	}
+
+	// the languages passing --near and --bbox, None when neither is given
+	fn located(&self, dataset: &Dataset) -> Result<Option<HashSet<String>>, String> {
+		if self.near.is_empty() && self.bbox.is_empty() {
+			return Ok(None);
+		}
+
+		let index = GeoIndex::new(dataset.languages.values());
+		let near = match self.near[..] {
+			[] => None,
+			[lat, lon, km] => Some(index.within_km((lat, lon), km).into_iter().map(|(id, _)| id).collect::<HashSet<_>>()),
+			_ => return Err("--near takes LAT,LON,KM".to_string()),
+		};
+		let bbox = match self.bbox[..] {
+			[] => None,
+			[south, west, north, east] => Some(index.in_bbox(south, west, north, east).into_iter().collect::<HashSet<_>>()),
+			_ => return Err("--bbox takes SOUTH,WEST,NORTH,EAST".to_string()),
+		};
+
+		Ok(Some(
+			dataset
+				.languages
+				.keys()
+				.filter(|id| near.as_ref().is_none_or(|ids| ids.contains(id.as_str())))
+				.filter(|id| bbox.as_ref().is_none_or(|ids| ids.contains(id.as_str())))
+				.cloned()
+				.collect(),
+		))
+	}
+
+	fn matches(&self, lang: &Language) -> bool {
+		let any = |wanted: &Vec<String>, got: Option<&String>| {
+			wanted.is_empty() || got.is_some_and(|g| wanted.contains(g))
+		};
+
+		let in_sample = match self.sample {
+			None => true,
+			Some(Sample::Hundred) => lang.samples_100 == Some(true),
+			Some(Sample::TwoHundred) => lang.samples_200 == Some(true),
+		};
+
+		// WALS lists every country of a language, e.g. "CA US"
+		let in_country = self.country.is_empty()
+			|| lang
+				.country_id
+				.as_deref()
+				.is_some_and(|cs| cs.split_whitespace().any(|c| self.country.iter().any(|w| w == c)));
+
+		in_sample
+			&& in_country
+			&& any(&self.family, lang.family.as_ref())
+			&& any(&self.genus, lang.genus.as_ref())
+			&& any(&self.macroarea, lang.macroarea.as_ref())
+	}
+}
+
+// ids, one per line; blank lines and lines starting with # are skipped
+fn read_lang_file(path: &PathBuf) -> Result<HashSet<String>, String> {
+	let text = fs::read_to_string(path).map_err(|e| format!("{e} @{}", path.display()))?;
+
+	Ok(text
+		.lines()
+		.map(|l| l.trim())
+		.filter(|l| !l.is_empty() && !l.starts_with('#'))
+		.map(|l| l.to_string())
+		.collect())
+}
+
+#[test]
+fn filter_toy() {
+	let filter = LanguageFilter {
+		sample: Some(Sample::Hundred),
+		macroarea: vec!["Eurasia".to_string()],
+		..Default::default()
+	};
+	let dataset = filter.apply(crate::dataset::toy()).unwrap();
+
+	let mut ids = dataset.languages.keys().cloned().collect::<Vec<_>>();
+	ids.sort();
+	assert_eq!(ids, ["aaa", "aab", "bba"]);
+	assert_eq!(dataset.values_of_parameter("P1").len(), 3);
+	assert_eq!(dataset.families(), ["F1"]);
+
+	let filter = LanguageFilter {
+		family: vec!["F9".to_string()],
+		..Default::default()
+	};
+	assert!(filter.apply(crate::dataset::toy()).is_err());
+
+	let filter = LanguageFilter {
+		near: vec![0.0, 30.0, 500.0],
+		..Default::default()
+	};
+	let mut ids = filter.apply(crate::dataset::toy()).unwrap().languages.into_keys().collect::<Vec<_>>();
+	ids.sort();
+	assert_eq!(ids, ["cca", "ccb"]);
+
+	let filter = LanguageFilter {
+		family: vec!["F2".to_string()],
+		genus: vec!["G4".to_string()],
+		..Default::default()
+	};
+	let dataset = crate::dataset::toy();
+	let mut ids = filter.group_members(&dataset).into_iter().collect::<Vec<_>>();
+	ids.sort();
+	assert_eq!(ids, ["cca", "ccb", "dda"]);
+	assert!(filter.selected(&dataset).is_err());
+	assert_eq!(filter.without_groups().selected(&dataset), Ok(None));
+}
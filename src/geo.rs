@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use crate::csvs::Language;
+
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+const CELL_DEGREES: f64 = 5.0;
+
+// (row, column) of a grid square
+type Cell = (i32, i32);
+// a language id and its (latitude, longitude)
+type Located = (String, (f64, f64));
+
+// haversine distance between two (latitude, longitude) points in degrees
+pub fn great_circle_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+	let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+	let dlat = lat2 - lat1;
+	let dlon = (b.1 - a.1).to_radians();
+
+	let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+	2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+pub fn coordinates(lang: &Language) -> Option<(f64, f64)> {
+	Some((widen(lang.latitude?), widen(lang.longitude?)))
+}
+
+// through the shortest decimal of the f32, so that 142.95 stays 142.95 and not 142.9499969...
+fn widen(x: f32) -> f64 {
+	x.to_string().parse().unwrap_or(x as f64)
+}
+
+// languages bucketed into a grid of CELL_DEGREES squares, so that a query only
+// measures the distance to those in the cells it overlaps
+#[derive(Debug, Clone, Default)]
+pub struct GeoIndex {
+	cells: HashMap<Cell, Vec<Located>>,
+}
+
+impl GeoIndex {
+	pub fn new<'a, I: IntoIterator<Item = &'a Language>>(langs: I) -> Self {
+		let mut cells: HashMap<Cell, Vec<Located>> = HashMap::new();
+		for lang in langs {
+			if let Some(p) = coordinates(lang) {
+				cells.entry(cell(p)).or_default().push((lang.id.clone(), p));
+			}
+		}
+
+		Self { cells }
+	}
+
+	// (language_id, km) of the languages within km of the point, nearest first
+	pub fn within_km(&self, center: (f64, f64), km: f64) -> Vec<(&str, f64)> {
+		let dlat = (km / EARTH_RADIUS_KM).to_degrees();
+		let (south, north) = (center.0 - dlat, center.0 + dlat);
+
+		// near a pole the circle spans every longitude
+		let widest = south.abs().max(north.abs());
+		let (west, east) = match widest < 90.0 && dlat / widest.to_radians().cos() < 180.0 {
+			true => {
+				let dlon = dlat / widest.to_radians().cos();
+				(center.1 - dlon, center.1 + dlon)
+			}
+			false => (-180.0, 180.0),
+		};
+
+		let mut found = self
+			.candidates(south, west, north, east)
+			.filter_map(|(id, p)| {
+				let d = great_circle_km(center, *p);
+				(d <= km).then_some((id.as_str(), d))
+			})
+			.collect::<Vec<_>>();
+		found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)));
+		found
+	}
+
+	// languages inside the box, sorted; west > east crosses the antimeridian
+	pub fn in_bbox(&self, south: f64, west: f64, north: f64, east: f64) -> Vec<&str> {
+		let east_unwrapped = if west > east { east + 360.0 } else { east };
+
+		let mut found = self
+			.candidates(south, west, north, east_unwrapped)
+			.filter(|(_, (lat, lon))| {
+				let in_lon = match west <= east {
+					true => (west..=east).contains(lon),
+					false => *lon >= west || *lon <= east,
+				};
+				(south..=north).contains(lat) && in_lon
+			})
+			.map(|(id, _)| id.as_str())
+			.collect::<Vec<_>>();
+		found.sort();
+		found
+	}
+
+	// everything in the cells overlapping the box, whose longitudes may run past 180
+	fn candidates(&self, south: f64, west: f64, north: f64, east: f64) -> impl Iterator<Item = &Located> {
+		let cols = (360.0 / CELL_DEGREES) as i32;
+		let (row0, col0) = cell((south.max(-90.0), west));
+		let (row1, col1) = cell((north.min(90.0), east));
+		let span = match east - west >= 360.0 {
+			true => cols - 1,
+			false => (col1 - col0).rem_euclid(cols),
+		};
+
+		(row0..=row1)
+			.flat_map(move |row| (0..=span).map(move |k| (row, (col0 + k).rem_euclid(cols))))
+			.filter_map(|c| self.cells.get(&c))
+			.flatten()
+	}
+}
+
+fn cell((lat, lon): (f64, f64)) -> Cell {
+	let rows = (180.0 / CELL_DEGREES) as i32;
+	let cols = (360.0 / CELL_DEGREES) as i32;
+	let row = (((lat + 90.0) / CELL_DEGREES).floor() as i32).clamp(0, rows - 1);
+	let col = (((lon + 180.0) / CELL_DEGREES).floor() as i32).rem_euclid(cols);
+	(row, col)
+}
+
+#[test]
+fn distances() {
+	assert!((great_circle_km((0.0, 0.0), (0.0, 90.0)) - 10007.5).abs() < 0.1);
+	assert!((great_circle_km((0.0, 179.0), (0.0, -179.0)) - 222.4).abs() < 0.1);
+	assert_eq!(great_circle_km((35.0, 139.0), (35.0, 139.0)), 0.0);
+	assert_eq!(widen(142.95), 142.95);
+
+	let dataset = crate::dataset::toy();
+	let index = GeoIndex::new(dataset.languages.values());
+	let near = index.within_km((50.0, 10.0), 200.0);
+	assert_eq!(near.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ["aaa", "aab"]);
+	assert_eq!(index.in_bbox(-5.0, 25.0, 5.0, 35.0), ["cca", "ccb"]);
+	assert_eq!(index.in_bbox(-20.0, 170.0, 60.0, 15.0), ["aaa", "aab", "dda"]);
+}
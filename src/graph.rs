@@ -1,5 +1,5 @@
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	fs::OpenOptions,
 	io::{BufWriter, Write},
 	path::Path,
@@ -12,12 +12,19 @@ use gnuplot::{
 };
 
 use crate::{
+	cluster::Tree,
 	collect::MyLanguage,
-	csvs::{read_csv, Chapter, Parameter},
-	util::{map_keys_dict, map_map_dict, rev_dict, transform_ddict, unzip_dict},
+	csvs::{Chapter, Codes, Parameter},
+	dataset::{Dataset, Grouping},
+	util::{ensure_dir, map_keys_dict, map_map_dict, rev_dict, transform_ddict, unzip_dict},
 };
 
 const POINT_SYMBOLS: &str = "+xtosdr";
+pub const PLOT_DIR: &str = "plot";
+// one per value on maps and bar charts
+pub const COLORS: [&str; 9] = [
+	"#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#a65628", "#f781bf", "#666666", "#17becf",
+];
 
 #[cfg(test)]
 mod tests {
@@ -72,26 +79,38 @@ impl GraphLine {
 		self.lang_ids.sort();
 	}
 
-	pub fn another_plot(self) -> Result<(), String> {
+	pub fn another_plot(mut self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		// no ids: every language left by the filters (--near, --family, ...)
+		if self.lang_ids.is_empty() {
+			self.lang_ids = dataset
+				.languages
+				.keys()
+				.filter(|id| !dataset.values_of_language(id).is_empty())
+				.cloned()
+				.collect();
+			self.sort_lang_ids();
+		}
+		if self.lang_ids.len() > POINT_SYMBOLS.len() {
+			return Err(format!(
+				"{} languages selected, at most {} can be plotted together",
+				self.lang_ids.len(),
+				POINT_SYMBOLS.len()
+			));
+		}
+
+		let plot_dir = ensure_dir(out_dir.join(PLOT_DIR))?;
 		let filename = self.filename_by_ids();
-		let mylangs = MyLanguage::get_my_languages_by_id(&self.lang_ids)?;
+		let mylangs = MyLanguage::get_my_languages_by_id(dataset, &self.lang_ids);
 
 		let mylangs = mylangs.values().collect::<Vec<_>>();
 
 		let gs: AnotherGraphSource = mylangs.into();
 		let gs = gs.sort_by_distinct_count_max_asc();
 
-		let param_map = read_csv::<Parameter, _>("parameters.csv")?
-			.into_iter()
-			.map(|p| (p.id.clone(), p))
-			.collect();
+		let param_map = &dataset.parameters;
+		let codes = &dataset.codes;
 
-		let chapter_map = read_csv::<Chapter, _>("chapters.csv")?
-			.into_iter()
-			.filter_map(|p| p.id.parse::<usize>().map(|n| (n, p)).ok())
-			.collect();
-
-		gs.write_param_number_id_map(format!("plot/{}-param.txt", &filename), &param_map, &chapter_map)?;
+		gs.write_param_number_id_map(plot_dir.join(format!("{}-param.txt", filename)), param_map, &dataset.chapters, codes)?;
 		let mut figure = Figure::new();
 		let ax = figure.axes2d();
 		ax.set_x_ticks(Some((AutoOption::Fix(10.0), 1)), &[], &[]);
@@ -102,7 +121,8 @@ impl GraphLine {
 		ax.set_y_grid(true);
 		ax.set_x_minor_grid(true);
 		ax.set_y_minor_grid(true);
-		gs.plot_param_number_id_map(ax, &param_map);
+		gs.plot_param_number_id_map(ax, param_map);
+		gs.plot_value_labels(ax, codes);
 		gs.plot(ax);
 
 		let op = GraphSaveOption {
@@ -111,11 +131,11 @@ impl GraphLine {
 		};
 
 		figure
-			.save_to_svg(format!("plot/{}.svg", filename), op.width_px, op.height_px)
+			.save_to_svg(plot_dir.join(format!("{}.svg", filename)), op.width_px, op.height_px)
 			.map_err(|e| format!("{e} @save_to_svg"))?;
 
 		figure
-			.save_to_png(format!("plot/{}.png", filename), op.width_px, op.height_px)
+			.save_to_png(plot_dir.join(format!("{}.png", filename)), op.width_px, op.height_px)
 			.map_err(|e| format!("{e} @save_to_png"))?;
 
 		Ok(())
@@ -174,6 +194,7 @@ pub struct GraphSource {
 pub struct AnotherGraphSource {
 	lang_params_map: HashMap<String, HashMap<String, usize>>,
 	param_id_number_map: HashMap<String, usize>,
+	param_number_value_map: HashMap<String, HashMap<usize, String>>, // <param_id, <plotted y, value>>
 }
 
 // impl GraphSource {
@@ -339,6 +360,7 @@ impl AnotherGraphSource {
 		Self {
 			param_id_number_map,
 			lang_params_map: self.lang_params_map,
+			param_number_value_map: self.param_number_value_map,
 		}
 	}
 	
@@ -347,6 +369,7 @@ impl AnotherGraphSource {
 		path: P,
 		param_map: &HashMap<String, Parameter>,
 		chapter_map: &HashMap<usize, Chapter>,
+		codes: &Codes,
 	) -> Result<(), String> {
 		dbg!(chapter_map);
 
@@ -366,7 +389,8 @@ impl AnotherGraphSource {
 
 			if let Some(param) = param_map.get(id) {
 				// dbg!(param);
-				line = format!("{line}{}", if let Some(chapter) = chapter_map.get(&param.chapter_id)
+				line = format!("{line}{}", if let Some(chapter_id) = param.chapter_id
+				&& let Some(chapter) = chapter_map.get(&chapter_id)
 				&& let Some(url) = chapter.url_in_citation() {
 					format!("[{}]({})", &param.name, url)
 				} else {
@@ -374,6 +398,14 @@ impl AnotherGraphSource {
 				});
 			}
 
+			if let Some(number_value) = self.param_number_value_map.get(id) {
+				let mut number_value = number_value.iter().collect::<Vec<_>>();
+				number_value.sort_by_key(|(n, _)| *n);
+				for (n, value) in number_value {
+					line = format!("{line}\n\t{}: {}", n, codes.label(id, value));
+				}
+			}
+
 			w.write_all((line + "\n").as_bytes());
 		});
 
@@ -400,6 +432,24 @@ impl AnotherGraphSource {
 		});
 	}
 
+	fn plot_value_labels(&self, ax: &mut Axes2D, codes: &Codes) {
+		for (param_id, number) in self.param_id_number_map.iter() {
+			let Some(number_value) = self.param_number_value_map.get(param_id) else {
+				continue;
+			};
+
+			for (y, value) in number_value {
+				let label = codes.name(param_id, value).unwrap_or(value);
+				ax.label(
+					label,
+					Coordinate::Axis(*number as f64),
+					Coordinate::Axis(*y as f64),
+					&[LabelOption::TextOffset(0.5, 0.0), LabelOption::Font("", 7.0)],
+				);
+			}
+		}
+	}
+
 	fn plot(self, ax: &mut Axes2D) -> Result<(), String> {
 		let chars = POINT_SYMBOLS.chars().collect::<Vec<_>>();
 		let cloneh = self.lang_params_map.clone();
@@ -440,11 +490,133 @@ impl AnotherGraphSource {
 	}
 }
 
+// leaves one below the other in tree order, each clade's vertical line at its
+// distance from the root
+pub fn plot_dendrogram(
+	tree: &Tree,
+	dataset: &Dataset,
+	grouping: Grouping,
+	out_dir: &Path,
+	filename: &str,
+) -> Result<(), String> {
+	let plot_dir = ensure_dir(out_dir.join(PLOT_DIR))?;
+	let mut segments = vec![];
+	let mut leaves = vec![];
+	dendrogram_layout(tree, 0.0, &mut segments, &mut leaves);
+
+	let mut figure = Figure::new();
+	let ax = figure.axes2d();
+	ax.set_y_ticks(None, &[], &[]);
+	ax.set_y_range(AutoOption::Fix(-(leaves.len() as f64)), AutoOption::Fix(1.0));
+	ax.set_x_label("distance", &[]);
+
+	for (xs, ys) in segments {
+		ax.lines(xs, ys, &[PlotOption::Color("black")]);
+	}
+
+	for (x, y, lang_id) in leaves.iter() {
+		let label = match dataset.language(lang_id) {
+			Some(lang) => format!(
+				"{} {} [{}]",
+				lang.id,
+				lang.name,
+				grouping.of(lang).map(|g| g.as_str()).unwrap_or("?")
+			),
+			None => lang_id.to_string(),
+		};
+		ax.label(
+			&label,
+			Coordinate::Axis(*x),
+			Coordinate::Axis(*y),
+			&[LabelOption::TextOffset(0.5, 0.0), LabelOption::Font("", 8.0)],
+		);
+	}
+
+	let op = GraphSaveOption {
+		width_px: 1280,
+		height_px: 100 + 14 * leaves.len() as u32,
+	};
+
+	figure
+		.save_to_svg(plot_dir.join(format!("{}.svg", filename)), op.width_px, op.height_px)
+		.map_err(|e| format!("{e} @save_to_svg"))?;
+
+	figure
+		.save_to_png(plot_dir.join(format!("{}.png", filename)), op.width_px, op.height_px)
+		.map_err(|e| format!("{e} @save_to_png"))?;
+
+	Ok(())
+}
+
+// returns the y of the node; leaves go downwards from 0
+fn dendrogram_layout<'a>(
+	tree: &'a Tree,
+	x: f64,
+	segments: &mut Vec<([f64; 2], [f64; 2])>,
+	leaves: &mut Vec<(f64, f64, &'a str)>,
+) -> f64 {
+	if tree.children.is_empty() {
+		let y = -(leaves.len() as f64);
+		leaves.push((x, y, tree.label.as_deref().unwrap_or_default()));
+		return y;
+	}
+
+	let ys = tree
+		.children
+		.iter()
+		.map(|(child, length)| {
+			let child_x = x + *length as f64;
+			let y = dendrogram_layout(child, child_x, segments, leaves);
+			segments.push(([x, child_x], [y, y]));
+			y
+		})
+		.collect::<Vec<_>>();
+
+	let top = ys.iter().copied().fold(f64::MIN, f64::max);
+	let bottom = ys.iter().copied().fold(f64::MAX, f64::min);
+	segments.push(([x, x], [bottom, top]));
+
+	(top + bottom) / 2.0
+}
+
+// plotted y of each (parameter, value); values of datasets whose values are not
+// integers (Grambank, APiCS, ...) are numbered by their sorted order instead
+fn value_numbers(langs: &[&MyLanguage]) -> HashMap<String, HashMap<String, usize>> {
+	let mut distinct: HashMap<String, HashSet<String>> = HashMap::new();
+	for lang in langs {
+		for (param_id, samevalue) in lang.param_values.iter() {
+			distinct
+				.entry(param_id.clone())
+				.or_default()
+				.insert(samevalue.value.clone());
+		}
+	}
+
+	distinct
+		.into_iter()
+		.map(|(param_id, values)| {
+			let numbers = if values.iter().all(|v| v.parse::<usize>().is_ok()) {
+				values
+					.into_iter()
+					.filter_map(|v| v.parse::<usize>().ok().map(|n| (v, n)))
+					.collect()
+			} else {
+				let mut values = values.into_iter().collect::<Vec<_>>();
+				values.sort();
+				values.into_iter().enumerate().map(|(i, v)| (v, i + 1)).collect()
+			};
+
+			(param_id, numbers)
+		})
+		.collect()
+}
+
 impl From<Vec<&MyLanguage>> for GraphSource {
 	fn from(value: Vec<&MyLanguage>) -> Self {
 		let mut param_number_id_map = HashMap::new();
 		let mut param_id_number_map = HashMap::new();
 		let mut param_number = 0;
+		let value_numbers = value_numbers(&value);
 
 		for lang in value.clone() {
 			for (param_id, samevalue) in lang.param_values.iter() {
@@ -466,7 +638,8 @@ impl From<Vec<&MyLanguage>> for GraphSource {
 						.iter()
 						.filter_map(|(param_id, samevalue)| {
 							let i = param_id_number_map.get(param_id)?;
-							Some((*i, samevalue.value))
+							let number = value_numbers.get(param_id)?.get(&samevalue.value)?;
+							Some((*i, *number))
 						})
 						.collect(),
 				)
@@ -485,6 +658,7 @@ impl From<Vec<&MyLanguage>> for AnotherGraphSource {
 		let mut param_number_id_map = HashMap::new();
 		let mut param_id_number_map = HashMap::new();
 		let mut param_number = 0;
+		let value_numbers = value_numbers(&value);
 
 		for lang in value.clone() {
 			for (param_id, samevalue) in lang.param_values.iter() {
@@ -504,15 +678,24 @@ impl From<Vec<&MyLanguage>> for AnotherGraphSource {
 					m.language.id.clone(),
 					m.param_values
 						.iter()
-						.map(|(param_id, samevalue)| (param_id.clone(), samevalue.value))
+						.filter_map(|(param_id, samevalue)| {
+							let number = value_numbers.get(param_id)?.get(&samevalue.value)?;
+							Some((param_id.clone(), *number))
+						})
 						.collect(),
 				)
 			})
 			.collect();
 
+		let param_number_value_map = value_numbers
+			.into_iter()
+			.map(|(param_id, numbers)| (param_id, rev_dict(numbers)))
+			.collect();
+
 		Self {
 			lang_params_map,
 			param_id_number_map,
+			param_number_value_map,
 		}
 	}
 }
@@ -0,0 +1,357 @@
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{
+	collect::{collect_values_per_language, ParamValueMap},
+	dataset::{Dataset, Grouping},
+	distance::{Comparer, DistanceOptions},
+	geo::{coordinates, great_circle_km},
+	util::{ensure_dir, Rng},
+};
+
+const IMPUTED_FILE: &str = "out/imputed.csv";
+const PROVENANCE_FILE: &str = "out/imputed-provenance.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImputeMethod {
+	/// Most common value within the genus, else within the family
+	Genus,
+	/// Most common value within the family
+	Family,
+	/// Most common value among the k typologically nearest languages
+	Knn,
+	/// Most common value among the k geographically nearest languages
+	Geo,
+}
+
+// where the value of a cell comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provenance {
+	Observed,
+	Genus,
+	Family,
+	Knn,
+	Geo,
+}
+
+impl Provenance {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Observed => "observed",
+			Self::Genus => "genus",
+			Self::Family => "family",
+			Self::Knn => "knn",
+			Self::Geo => "geo",
+		}
+	}
+}
+
+/// Fill the missing cells of the language by parameter matrix and report held-out accuracy
+#[derive(Debug, Args)]
+pub struct Impute {
+	#[arg(long, value_enum, default_value_t = ImputeMethod::Genus)]
+	method: ImputeMethod,
+
+	/// Neighbours voting with knn and geo
+	#[arg(long, default_value_t = 5)]
+	k: usize,
+
+	/// Share of the observed cells hidden and imputed to measure accuracy, 0 to skip
+	#[arg(long, default_value_t = 0.1)]
+	holdout: f32,
+
+	#[arg(long, default_value_t = 1)]
+	seed: u64,
+
+	/// Only these parameters, e.g. 81A,82A,83A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	#[command(flatten)]
+	options: DistanceOptions,
+}
+
+pub struct Imputer<'a> {
+	dataset: &'a Dataset,
+	method: ImputeMethod,
+	k: usize,
+	observed: HashMap<String, ParamValueMap>,
+	neighbours: HashMap<String, Vec<String>>, // nearest first, for knn and geo
+}
+
+impl<'a> Imputer<'a> {
+	pub fn new(
+		dataset: &'a Dataset,
+		observed: HashMap<String, ParamValueMap>,
+		method: ImputeMethod,
+		k: usize,
+		options: &DistanceOptions,
+	) -> Result<Self, String> {
+		let mut lang_ids = observed.keys().cloned().collect::<Vec<_>>();
+		lang_ids.sort();
+
+		let neighbours = match method {
+			ImputeMethod::Genus | ImputeMethod::Family => HashMap::new(),
+			ImputeMethod::Knn => {
+				let comparer = Comparer::new(dataset, options)?.with_values(observed.clone());
+				nearest(&lang_ids, |a, b| comparer.compare(a, b).map(|(d, _)| d as f64))
+			}
+			ImputeMethod::Geo => {
+				let coords: HashMap<&String, (f64, f64)> = lang_ids
+					.iter()
+					.filter_map(|id| Some((id, coordinates(dataset.language(id)?)?)))
+					.collect();
+				nearest(&lang_ids, |a, b| Some(great_circle_km(*coords.get(a)?, *coords.get(b)?)))
+			}
+		};
+
+		Ok(Self {
+			dataset,
+			method,
+			k,
+			observed,
+			neighbours,
+		})
+	}
+
+	pub fn observed(&self, lang_id: &str, param_id: &str) -> Option<&String> {
+		self.observed.get(lang_id)?.get(param_id)
+	}
+
+	pub fn predict(&self, lang_id: &str, param_id: &str) -> Option<(String, Provenance)> {
+		match self.method {
+			ImputeMethod::Genus => self
+				.group_mode(Grouping::Genus, lang_id, param_id)
+				.map(|v| (v, Provenance::Genus))
+				.or_else(|| self.group_mode(Grouping::Family, lang_id, param_id).map(|v| (v, Provenance::Family))),
+			ImputeMethod::Family => self
+				.group_mode(Grouping::Family, lang_id, param_id)
+				.map(|v| (v, Provenance::Family)),
+			ImputeMethod::Knn => self.neighbour_mode(lang_id, param_id).map(|v| (v, Provenance::Knn)),
+			ImputeMethod::Geo => self.neighbour_mode(lang_id, param_id).map(|v| (v, Provenance::Geo)),
+		}
+	}
+
+	fn group_mode(&self, grouping: Grouping, lang_id: &str, param_id: &str) -> Option<String> {
+		let group = grouping.of(self.dataset.language(lang_id)?)?;
+		let values = self
+			.dataset
+			.languages_of_group(grouping, group)
+			.iter()
+			.filter(|id| *id != lang_id)
+			.filter_map(|id| self.observed(id, param_id));
+
+		mode(values)
+	}
+
+	fn neighbour_mode(&self, lang_id: &str, param_id: &str) -> Option<String> {
+		let values = self
+			.neighbours
+			.get(lang_id)?
+			.iter()
+			.filter_map(|id| self.observed(id, param_id))
+			.take(self.k);
+
+		mode(values)
+	}
+}
+
+// the other languages of each by increasing distance, leaving out those without one
+fn nearest<F: Fn(&String, &String) -> Option<f64>>(lang_ids: &[String], distance: F) -> HashMap<String, Vec<String>> {
+	lang_ids
+		.iter()
+		.map(|a| {
+			let mut others = lang_ids
+				.iter()
+				.filter(|b| *b != a)
+				.filter_map(|b| distance(a, b).map(|d| (d, b)))
+				.collect::<Vec<_>>();
+			others.sort_by(|x, y| x.0.total_cmp(&y.0).then(x.1.cmp(y.1)));
+			(a.clone(), others.into_iter().map(|(_, b)| b.clone()).collect())
+		})
+		.collect()
+}
+
+// most frequent value; ties go to the value seen first, so nearer neighbours win
+fn mode<'v, I: Iterator<Item = &'v String>>(values: I) -> Option<String> {
+	let mut counts: Vec<(&String, usize)> = vec![];
+	for v in values {
+		match counts.iter_mut().find(|(c, _)| *c == v) {
+			Some((_, n)) => *n += 1,
+			None => counts.push((v, 1)),
+		}
+	}
+
+	let max = counts.iter().map(|(_, n)| *n).max()?;
+	counts.into_iter().find(|(_, n)| *n == max).map(|(v, _)| v.clone())
+}
+
+impl Impute {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let mut param_ids = match self.params.is_empty() {
+			true => dataset.parameters.keys().cloned().collect::<Vec<_>>(),
+			false => self.params.clone(),
+		};
+		param_ids.sort();
+
+		let observed = self.observed(dataset, &param_ids);
+		let mut lang_ids = observed.keys().cloned().collect::<Vec<_>>();
+		lang_ids.sort();
+
+		if self.holdout > 0.0 {
+			self.report_holdout(dataset, &observed)?;
+		}
+
+		let imputer = Imputer::new(dataset, observed, self.method, self.k, &self.options)?;
+
+		let (mut values, mut provenance) = (vec![], vec![]);
+		let (mut observed_cells, mut filled) = (0, 0);
+		for lang_id in lang_ids.iter() {
+			let (mut vs, mut ps) = (vec![lang_id.clone()], vec![lang_id.clone()]);
+			for param_id in param_ids.iter() {
+				let cell = match imputer.observed(lang_id, param_id) {
+					Some(v) => Some((v.clone(), Provenance::Observed)),
+					None => imputer.predict(lang_id, param_id),
+				};
+				match cell.as_ref().map(|(_, p)| *p) {
+					Some(Provenance::Observed) => observed_cells += 1,
+					Some(_) => filled += 1,
+					None => {}
+				}
+
+				let (v, p) = cell.map(|(v, p)| (v, p.as_str())).unwrap_or_default();
+				vs.push(v);
+				ps.push(p.to_string());
+			}
+			values.push(vs);
+			provenance.push(ps);
+		}
+
+		let header = std::iter::once("language_id".to_string())
+			.chain(param_ids.iter().cloned())
+			.collect::<Vec<_>>();
+		for (file, rows) in [(IMPUTED_FILE, values), (PROVENANCE_FILE, provenance)] {
+			let path = out_dir.join(file);
+			if let Some(dir) = path.parent() {
+				ensure_dir(dir)?;
+			}
+
+			let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+			for row in std::iter::once(&header).chain(rows.iter()) {
+				w.write_record(row).map_err(|e| format!("{e} @{}", path.display()))?;
+			}
+			w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+		}
+
+		let cells = lang_ids.len() * param_ids.len();
+		println!(
+			"{} languages x {} parameters: {} observed, {} imputed, {} still missing; written to {}",
+			lang_ids.len(),
+			param_ids.len(),
+			observed_cells,
+			filled,
+			cells - observed_cells - filled,
+			out_dir.join(IMPUTED_FILE).display()
+		);
+
+		Ok(())
+	}
+
+	fn observed(&self, dataset: &Dataset, param_ids: &[String]) -> HashMap<String, ParamValueMap> {
+		let param_ids = param_ids.iter().collect::<HashSet<_>>();
+
+		collect_values_per_language(dataset)
+			.into_iter()
+			.map(|(lang_id, pv)| (lang_id, pv.into_iter().filter(|(p, _)| param_ids.contains(p)).collect::<ParamValueMap>()))
+			.filter(|(_, pv)| !pv.is_empty())
+			.collect()
+	}
+
+	// hides a share of the observed cells, imputes them from the rest and compares
+	fn report_holdout(&self, dataset: &Dataset, observed: &HashMap<String, ParamValueMap>) -> Result<(), String> {
+		let mut cells = observed
+			.iter()
+			.flat_map(|(l, pv)| pv.keys().map(move |p| (l.clone(), p.clone())))
+			.collect::<Vec<_>>();
+		cells.sort();
+		Rng::new(self.seed).shuffle(&mut cells);
+		cells.truncate((cells.len() as f32 * self.holdout.min(1.0)).round() as usize);
+
+		let mut masked = observed.clone();
+		for (l, p) in cells.iter() {
+			if let Some(pv) = masked.get_mut(l) {
+				pv.remove(p);
+			}
+		}
+
+		let imputer = Imputer::new(dataset, masked, self.method, self.k, &self.options)?;
+
+		// <provenance, (predicted, correct)>
+		let mut scores: HashMap<Provenance, (usize, usize)> = HashMap::new();
+		for (l, p) in cells.iter() {
+			if let Some((v, provenance)) = imputer.predict(l, p) {
+				let score = scores.entry(provenance).or_default();
+				score.0 += 1;
+				if Some(&v) == observed.get(l).and_then(|pv| pv.get(p)) {
+					score.1 += 1;
+				}
+			}
+		}
+
+		let predicted: usize = scores.values().map(|s| s.0).sum();
+		let correct: usize = scores.values().map(|s| s.1).sum();
+		println!(
+			"held out {} cells: {} imputed ({:.1}%), {:.1}% of them correctly",
+			cells.len(),
+			predicted,
+			100.0 * predicted as f32 / cells.len().max(1) as f32,
+			100.0 * correct as f32 / predicted.max(1) as f32
+		);
+
+		let mut scores = scores.into_iter().collect::<Vec<_>>();
+		scores.sort_by_key(|(p, _)| p.as_str());
+		for (provenance, (predicted, correct)) in scores {
+			println!(
+				"\t{}: {} imputed, {:.1}% correct",
+				provenance.as_str(),
+				predicted,
+				100.0 * correct as f32 / predicted.max(1) as f32
+			);
+		}
+
+		Ok(())
+	}
+}
+
+#[test]
+fn impute_toy() {
+	use crate::distance::Metric;
+
+	let dataset = crate::dataset::toy();
+	let options = DistanceOptions {
+		metric: Metric::Hamming,
+		min_overlap: Some(1),
+		weights: None,
+		ordinal: vec![],
+	};
+	let observed = collect_values_per_language(&dataset);
+
+	let genus = Imputer::new(&dataset, observed.clone(), ImputeMethod::Genus, 1, &options).unwrap();
+	assert_eq!(genus.predict("aab", "P3"), Some(("1".to_string(), Provenance::Genus)));
+	assert_eq!(genus.predict("ccb", "P3"), Some(("2".to_string(), Provenance::Genus)));
+	assert_eq!(genus.predict("dda", "P2"), None);
+
+	let knn = Imputer::new(&dataset, observed.clone(), ImputeMethod::Knn, 1, &options).unwrap();
+	assert_eq!(knn.predict("dda", "P2"), Some(("2".to_string(), Provenance::Knn)));
+
+	let geo = Imputer::new(&dataset, observed, ImputeMethod::Geo, 1, &options).unwrap();
+	assert_eq!(geo.predict("aab", "P3"), Some(("1".to_string(), Provenance::Geo)));
+}
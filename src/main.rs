@@ -9,52 +9,300 @@
 )]
 #![allow(dead_code)]
 
-use collect::{
-	collect_values_per_language, collect_values_per_param, get_my_languages, MyLanguage,
-};
+use aggregate::{Aggregate, FamilyProfile};
+use areal::Areal;
+use bib::{BibFormat, Bibliography};
+use collect::{collect_examples_per_value, collect_sources_per_language, get_my_languages};
 
+mod aggregate;
+mod areal;
+mod bib;
+mod cluster;
 mod collect;
+mod correlate;
+mod coverage;
 mod csvs;
+mod dataset;
+mod distance;
+mod export;
+mod filter;
+mod geo;
 mod graph;
+mod impute;
+mod map;
+mod metadata;
+mod nexus;
+mod query;
+mod rarity;
+mod sampling;
+mod stats;
+mod universals;
 mod util;
 
-use clap::{Parser, Subcommand};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand};
+use cluster::Cluster;
+use correlate::Correlate;
+use coverage::Coverage;
+use dataset::Dataset;
+use distance::{Distance, Similar};
+use export::GeoExport;
+use filter::LanguageFilter;
 use graph::GraphLine;
+use impute::Impute;
+use map::Map;
+use metadata::Metadata;
+use nexus::Nexus;
+use query::{Bbox, Near};
+use rarity::Rarity;
+use sampling::Sampling;
+use universals::Universals;
+use util::ensure_dir;
+
+const LANGS_DIR: &str = "out/langs";
 
 #[derive(Debug, Parser)]
 struct Cli {
+	/// Directory of the CLDF dataset, or its metadata json
+	#[arg(long, global = true, env = "CLDF_DATA_DIR", default_value = "cldf")]
+	data_dir: PathBuf,
+
+	/// Directory under which out/langs and plot are written
+	#[arg(long, global = true, env = "CLDF_OUT_DIR", default_value = ".")]
+	out_dir: PathBuf,
+
+	#[command(flatten)]
+	filter: LanguageFilter,
+
 	#[command(subcommand)]
 	command: Subcommands,
 }
 
 #[derive(Debug, Subcommand)]
 enum Subcommands {
-	WriteCSV,
+	WriteCSV(WriteCSV),
 	GraphLine(GraphLine),
+	Example(ShowExample),
+	Cite(Cite),
+	Rarity(Rarity),
+	Distance(Distance),
+	Similar(Similar),
+	Cluster(Cluster),
+	Nexus(Nexus),
+	Universals(Universals),
+	Correlate(Correlate),
+	Sample(Sampling),
+	Impute(Impute),
+	Coverage(Coverage),
+	Map(Map),
+	GeoExport(GeoExport),
+	Areal(Areal),
+	Near(Near),
+	Bbox(Bbox),
+	Aggregate(Aggregate),
+	FamilyProfile(FamilyProfile),
+}
+
+#[derive(Debug, Args)]
+struct WriteCSV {
+	/// Add the interlinear examples linked to each value
+	#[arg(long)]
+	examples: bool,
+
+	/// Also write each language's bibliography next to its csv
+	#[arg(long, value_enum)]
+	bib: Option<BibFormat>,
+}
+
+/// Print the interlinear examples linked to a value
+#[derive(Debug, Args)]
+struct ShowExample {
+	value_id: String,
+}
+
+/// Print the bibliography of a language and its values
+#[derive(Debug, Args)]
+struct Cite {
+	lang_id: String,
+
+	#[arg(long, value_enum, default_value_t = BibFormat::Text)]
+	format: BibFormat,
 }
 
 fn main() {
 	let cli = Cli::parse();
 
+	// similar and nexus select their languages with the filter themselves
+	let dataset = match Metadata::find(&cli.data_dir).and_then(Dataset::load).and_then(|d| match &cli.command {
+		Subcommands::Similar(_) | Subcommands::Nexus(_) => Ok(d),
+		_ => cli.filter.apply(d),
+	}) {
+		Ok(d) => d,
+		Err(e) => {
+			println!("{e}");
+			return;
+		}
+	};
+
 	match cli.command {
-		Subcommands::WriteCSV => {
-			write_per_lang_csv();
+		Subcommands::WriteCSV(a) => {
+			write_per_lang_csv(&dataset, &cli.out_dir, a).inspect_err(|e| println!("{e}"));
 		}
 
 		Subcommands::GraphLine(a) => {
-			a.another_plot().inspect_err(|e| println!("{e}"));
+			a.another_plot(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Example(a) => {
+			print_examples(&dataset, &a.value_id).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Cite(a) => {
+			print_bibliography(&dataset, a).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Rarity(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Distance(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Similar(a) => {
+			a.print(&dataset, &cli.filter).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Cluster(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Nexus(a) => {
+			a.write(&dataset, &cli.filter, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Universals(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Correlate(a) => {
+			a.print(&dataset).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Sample(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Impute(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Coverage(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Map(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::GeoExport(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Areal(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Near(a) => {
+			a.print(&dataset).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Bbox(a) => {
+			a.print(&dataset).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::Aggregate(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
+		}
+
+		Subcommands::FamilyProfile(a) => {
+			a.write(&dataset, &cli.out_dir).inspect_err(|e| println!("{e}"));
 		}
 	}
 }
 
-fn write_per_lang_csv() -> Result<(), String> {
-	let collected_values_per_param = collect_values_per_param()?;
-	let collected_values_per_lang = collect_values_per_language()?;
-	let mylangs = get_my_languages(collected_values_per_param, collected_values_per_lang)?;
+fn write_per_lang_csv(dataset: &Dataset, out_dir: &Path, option: WriteCSV) -> Result<(), String> {
+	let langs_dir = ensure_dir(out_dir.join(LANGS_DIR))?;
+	let mylangs = get_my_languages(dataset);
+	let examples = match option.examples {
+		true => Some(collect_examples_per_value(dataset)),
+		false => None,
+	};
+
+	let sources = match option.bib {
+		Some(_) => Some((Bibliography::read(&dataset.metadata)?, collect_sources_per_language(dataset))),
+		None => None,
+	};
+
+	let mut errors = vec![];
 
 	for (lang_id, mylang) in mylangs {
-		let path = format!("out/langs/{}.csv", lang_id);
-		mylang.write_to_csv(path);
+		let path = langs_dir.join(format!("{}.csv", lang_id));
+		if let Err(e) = mylang.write_to_csv(dataset, path, examples.as_ref()) {
+			errors.push(e);
+		}
+
+		if let Some(format) = option.bib
+			&& let Some((bib, sources)) = &sources
+		{
+			let refs = sources.get(&lang_id).map(|v| v.as_slice()).unwrap_or_default();
+			let path = langs_dir.join(format!("{}.{}", lang_id, format.extension()));
+			if let Err(e) = fs::write(&path, bib.format(refs, format)) {
+				errors.push(format!("{e} @{}", path.display()));
+			}
+		}
+	}
+
+	match errors.is_empty() {
+		true => Ok(()),
+		false => Err(errors.join("\n")),
+	}
+}
+
+fn print_bibliography(dataset: &Dataset, option: Cite) -> Result<(), String> {
+	let bib = Bibliography::read(&dataset.metadata)?;
+	let sources = collect_sources_per_language(dataset);
+	let refs = sources
+		.get(&option.lang_id)
+		.ok_or_else(|| format!("language {} not found", option.lang_id))?;
+
+	print!("{}", bib.format(refs, option.format));
+
+	Ok(())
+}
+
+fn print_examples(dataset: &Dataset, value_id: &str) -> Result<(), String> {
+	let value = dataset
+		.value(value_id)
+		.ok_or_else(|| format!("value {value_id} not found"))?;
+
+	let linked = value
+		.example_ids
+		.iter()
+		.filter_map(|id| dataset.examples.get(id))
+		.collect::<Vec<_>>();
+
+	if linked.is_empty() {
+		println!("no examples linked to {value_id}");
+	}
+
+	for example in linked {
+		println!("{} ({})", example.id, example.language_id);
+		println!("{}\n", example.interlinear());
 	}
 
 	Ok(())
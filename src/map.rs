@@ -0,0 +1,209 @@
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use gnuplot::{AlignType, AutoOption, AxesCommon, Coordinate, Figure, LabelOption, LegendOption, PlotOption};
+
+use crate::{
+	dataset::Dataset,
+	geo::coordinates,
+	graph::{COLORS, PLOT_DIR},
+	util::ensure_dir,
+};
+
+const MAP_SYMBOLS: &str = "OTSDRo";
+// the meridian WALS maps are centred on when showing the Pacific in one piece
+const PACIFIC_CENTER: f64 = 150.0;
+
+// x and y scale of the Robinson projection every 5 degrees of latitude
+const ROBINSON: [(f64, f64); 19] = [
+	(1.0000, 0.0000),
+	(0.9986, 0.0620),
+	(0.9954, 0.1240),
+	(0.9900, 0.1860),
+	(0.9822, 0.2480),
+	(0.9730, 0.3100),
+	(0.9600, 0.3720),
+	(0.9427, 0.4340),
+	(0.9216, 0.4958),
+	(0.8962, 0.5571),
+	(0.8679, 0.6176),
+	(0.8350, 0.6769),
+	(0.7986, 0.7346),
+	(0.7597, 0.7903),
+	(0.7186, 0.8435),
+	(0.6732, 0.8936),
+	(0.6213, 0.9394),
+	(0.5722, 0.9761),
+	(0.5322, 1.0000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Projection {
+	Equirectangular,
+	Robinson,
+}
+
+impl Projection {
+	// plot coordinates of a point, both in degree-like units, the central meridian at x = 0
+	pub fn project(&self, lat: f64, lon: f64, center: f64) -> (f64, f64) {
+		let lon = match lon - center {
+			d if d > 180.0 => d - 360.0,
+			d if d < -180.0 => d + 360.0,
+			d => d,
+		};
+		let lat = lat.clamp(-90.0, 90.0);
+
+		match self {
+			Self::Equirectangular => (lon, lat),
+			Self::Robinson => {
+				let i = ((lat.abs() / 5.0).floor() as usize).min(ROBINSON.len() - 2);
+				let t = lat.abs() / 5.0 - i as f64;
+				let (x0, y0) = ROBINSON[i];
+				let (x1, y1) = ROBINSON[i + 1];
+				let x = x0 + (x1 - x0) * t;
+				let y = y0 + (y1 - y0) * t;
+				(0.8487 * x * lon, 1.3523 * y * 1.0_f64.to_degrees() * lat.signum())
+			}
+		}
+	}
+}
+
+/// Plot the languages coded for a parameter on a world map, one colour and symbol per value
+#[derive(Debug, Args)]
+pub struct Map {
+	param_id: String,
+
+	#[arg(long, value_enum, default_value_t = Projection::Robinson)]
+	projection: Projection,
+
+	/// Centre the map on the Pacific instead of Greenwich
+	#[arg(long)]
+	pacific: bool,
+}
+
+impl Map {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let Some(param) = dataset.parameters.get(&self.param_id) else {
+			return Err(format!("parameter {} not found", self.param_id));
+		};
+		let center = if self.pacific { PACIFIC_CENTER } else { 0.0 };
+
+		let values = dataset.parameter_values(&self.param_id);
+
+		let plot_dir = ensure_dir(out_dir.join(PLOT_DIR))?;
+		let mut figure = Figure::new();
+		figure.set_title(&format!("{} {}", param.id, param.name));
+		let ax = figure.axes2d();
+		let (x_max, _) = self.projection.project(0.0, center + 180.0, center);
+		let (_, y_max) = self.projection.project(90.0, center, center);
+		ax.set_x_range(AutoOption::Fix(-x_max * 1.01), AutoOption::Fix(x_max * 1.01));
+		ax.set_y_range(AutoOption::Fix(-y_max * 1.01), AutoOption::Fix(y_max * 1.01));
+		ax.set_x_ticks(None, &[], &[]);
+		ax.set_y_ticks(None, &[], &[]);
+		ax.set_border(false, &[], &[]);
+		ax.set_legend(
+			Coordinate::Graph(0.01),
+			Coordinate::Graph(0.01),
+			&[LegendOption::Placement(AlignType::AlignLeft, AlignType::AlignBottom)],
+			&[LabelOption::Font("", 9.0)],
+		);
+
+		for (xs, ys) in graticule(self.projection, center) {
+			ax.lines(xs, ys, &[PlotOption::Color("#cccccc")]);
+		}
+
+		let symbols = MAP_SYMBOLS.chars().collect::<Vec<_>>();
+		let (mut plotted, mut unlocated) = (0, 0);
+		for (i, value) in values.iter().enumerate() {
+			let lang_ids = dataset
+				.languages_with_value(&self.param_id, value)
+				.map(|ls| ls.iter().collect::<Vec<_>>())
+				.unwrap_or_default();
+			let points = lang_ids
+				.iter()
+				.filter_map(|id| coordinates(dataset.language(id)?))
+				.map(|(lat, lon)| self.projection.project(lat, lon, center))
+				.collect::<Vec<_>>();
+			unlocated += lang_ids.len() - points.len();
+			plotted += points.len();
+			if points.is_empty() {
+				continue;
+			}
+
+			let caption = format!(
+				"{} ({})",
+				dataset.codes.name(&self.param_id, value).unwrap_or(value),
+				points.len()
+			);
+			let (xs, ys): (Vec<_>, Vec<_>) = points.into_iter().unzip();
+			ax.points(
+				xs,
+				ys,
+				&[
+					PlotOption::Caption(&caption),
+					PlotOption::PointSymbol(symbols[i % symbols.len()]),
+					PlotOption::Color(COLORS[i % COLORS.len()]),
+					PlotOption::PointSize(0.8),
+				],
+			);
+		}
+
+		let filename = format!(
+			"map-{}-{}{}",
+			self.param_id,
+			self.projection.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default(),
+			if self.pacific { "-pacific" } else { "" }
+		);
+		let (width_px, height_px) = (1600, 900);
+
+		figure
+			.save_to_svg(plot_dir.join(format!("{}.svg", filename)), width_px, height_px)
+			.map_err(|e| format!("{e} @save_to_svg"))?;
+
+		figure
+			.save_to_png(plot_dir.join(format!("{}.png", filename)), width_px, height_px)
+			.map_err(|e| format!("{e} @save_to_png"))?;
+
+		println!(
+			"{plotted} languages plotted, {unlocated} without coordinates, written to {}.{{svg,png}}",
+			plot_dir.join(&filename).display()
+		);
+
+		Ok(())
+	}
+}
+
+// parallels and meridians every 30 degrees, the outermost meridians making the outline
+fn graticule(projection: Projection, center: f64) -> Vec<(Vec<f64>, Vec<f64>)> {
+	let mut lines = vec![];
+
+	for lat in (-60..=60).step_by(30) {
+		let (xs, ys) = (-180..=180)
+			.map(|dlon| projection.project(lat as f64, center + dlon as f64, center))
+			.unzip();
+		lines.push((xs, ys));
+	}
+
+	for dlon in (-180..=180).step_by(30) {
+		let lon = center + dlon as f64;
+		let (xs, ys) = (-90..=90).map(|lat| projection.project(lat as f64, lon, center)).unzip();
+		lines.push((xs, ys));
+	}
+
+	lines
+}
+
+#[test]
+fn projections() {
+	let p = Projection::Equirectangular;
+	assert_eq!(p.project(35.0, 139.0, 0.0), (139.0, 35.0));
+	assert_eq!(p.project(35.0, 139.0, PACIFIC_CENTER), (-11.0, 35.0));
+	assert_eq!(p.project(0.0, -100.0, PACIFIC_CENTER), (110.0, 0.0));
+	assert_eq!(p.project(0.0, -30.0, PACIFIC_CENTER), (-180.0, 0.0));
+
+	let p = Projection::Robinson;
+	let (x, y) = p.project(0.0, 90.0, 0.0);
+	assert!((x - 0.8487 * 90.0).abs() < 1e-9 && y == 0.0);
+	let (x, y) = p.project(-90.0, 0.0, 0.0);
+	assert!(x.abs() < 1e-9 && (y + 1.3523 * 1.0_f64.to_degrees()).abs() < 1e-9);
+}
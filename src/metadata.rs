@@ -0,0 +1,278 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::BufReader,
+	path::{Path, PathBuf},
+};
+
+use csv::StringRecord;
+use serde::Deserialize;
+
+const TERMS_PREFIX: &str = "http://cldf.clld.org/v1.0/terms.rdf#";
+const METADATA_SUFFIX: &str = "-metadata.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+	Values,
+	Languages,
+	Parameters,
+	Codes,
+	Examples,
+	Contributions,
+}
+
+impl Component {
+	pub fn term(&self) -> &'static str {
+		match self {
+			Self::Values => "ValueTable",
+			Self::Languages => "LanguageTable",
+			Self::Parameters => "ParameterTable",
+			Self::Codes => "CodeTable",
+			Self::Examples => "ExampleTable",
+			Self::Contributions => "ContributionTable",
+		}
+	}
+
+	pub fn default_url(&self) -> &'static str {
+		match self {
+			Self::Values => "values.csv",
+			Self::Languages => "languages.csv",
+			Self::Parameters => "parameters.csv",
+			Self::Codes => "codes.csv",
+			Self::Examples => "examples.csv",
+			Self::Contributions => "contributions.csv",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Metadata {
+	#[serde(skip)]
+	pub dir: PathBuf,
+	#[serde(rename = "dc:conformsTo")]
+	pub conforms_to: Option<String>,
+	#[serde(rename = "dc:title")]
+	pub title: Option<String>,
+	#[serde(rename = "dc:source")]
+	pub source: Option<String>,
+	#[serde(default)]
+	pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Table {
+	pub url: String,
+	#[serde(rename = "dc:conformsTo")]
+	pub conforms_to: Option<String>,
+	#[serde(rename = "tableSchema")]
+	pub table_schema: TableSchema,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableSchema {
+	#[serde(default)]
+	pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Column {
+	pub name: String,
+	#[serde(rename = "propertyUrl")]
+	pub property_url: Option<String>,
+	pub null: Option<Nulls>,
+	pub separator: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Nulls {
+	One(String),
+	Many(Vec<String>),
+}
+
+impl Nulls {
+	fn to_vec(&self) -> Vec<String> {
+		match self {
+			Self::One(s) => vec![s.clone()],
+			Self::Many(v) => v.clone(),
+		}
+	}
+}
+
+impl Column {
+	pub fn term(&self) -> Option<&str> {
+		self.property_url.as_deref()?.strip_prefix(TERMS_PREFIX)
+	}
+}
+
+impl Metadata {
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+		let path = path.as_ref();
+		let f = File::open(path).map_err(|e| format!("{e} @File::open/Metadata::open"))?;
+		let mut metadata: Self = serde_json::from_reader(BufReader::new(f))
+			.map_err(|e| format!("{e} @serde_json/Metadata::open"))?;
+		metadata.dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+
+		Ok(metadata)
+	}
+
+	// accepts either the metadata file itself or the directory containing it
+	pub fn find<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+		let path = path.as_ref();
+		if path.is_file() {
+			return Self::open(path);
+		}
+
+		let mut candidates = fs::read_dir(path)
+			.map_err(|e| format!("{e} @read_dir/Metadata::find"))?
+			.filter_map(|entry| entry.ok().map(|e| e.path()))
+			.filter(|p| {
+				p.file_name()
+					.and_then(|n| n.to_str())
+					.is_some_and(|n| n.ends_with(METADATA_SUFFIX))
+			})
+			.collect::<Vec<_>>();
+		candidates.sort();
+
+		match candidates.first() {
+			Some(p) => Self::open(p),
+			None => Err(format!("no *{} in {}", METADATA_SUFFIX, path.display())),
+		}
+	}
+
+	pub fn table(&self, component: Component) -> Option<&Table> {
+		self
+			.tables
+			.iter()
+			.find(|t| t.conforms_to.as_deref().and_then(|c| c.strip_prefix(TERMS_PREFIX)) == Some(component.term()))
+			.or_else(|| self.tables.iter().find(|t| t.url == component.default_url()))
+	}
+
+	pub fn table_path(&self, table: &Table) -> PathBuf {
+		self.dir.join(&table.url)
+	}
+}
+
+// resolves a key, either a CLDF term like "languageReference" or a plain column name,
+// to the position of that column in the csv header
+#[derive(Debug, Clone, Default)]
+pub struct Columns {
+	terms: HashMap<String, usize>,
+	names: HashMap<String, usize>,
+	nulls: HashMap<usize, Vec<String>>, // csvw defaults to [""] for undeclared columns
+	separators: HashMap<usize, String>,
+}
+
+impl Columns {
+	pub fn new(table: &Table, headers: &StringRecord) -> Self {
+		let names: HashMap<String, usize> = headers
+			.iter()
+			.enumerate()
+			.map(|(i, h)| (h.to_lowercase(), i))
+			.collect();
+
+		let mut terms = HashMap::new();
+		let mut nulls = HashMap::new();
+		let mut separators = HashMap::new();
+		for column in table.table_schema.columns.iter() {
+			let Some(i) = names.get(&column.name.to_lowercase()) else {
+				continue;
+			};
+
+			if let Some(term) = column.term() {
+				terms.entry(term.to_string()).or_insert(*i);
+			}
+
+			if let Some(null) = &column.null {
+				nulls.insert(*i, null.to_vec());
+			}
+
+			if let Some(separator) = &column.separator {
+				separators.insert(*i, separator.clone());
+			}
+		}
+
+		Self {
+			terms,
+			names,
+			nulls,
+			separators,
+		}
+	}
+
+	pub fn is_null(&self, index: usize, cell: &str) -> bool {
+		match self.nulls.get(&index) {
+			Some(nulls) => nulls.iter().any(|n| n == cell),
+			None => cell.is_empty(),
+		}
+	}
+
+	pub fn index(&self, key: &str) -> Option<usize> {
+		self
+			.terms
+			.get(key)
+			.or_else(|| self.names.get(&key.to_lowercase()))
+			.copied()
+	}
+}
+
+pub struct Record<'a> {
+	pub record: &'a StringRecord,
+	pub columns: &'a Columns,
+}
+
+impl<'a> Record<'a> {
+	pub fn get(&self, key: &str) -> Option<&'a str> {
+		let i = self.columns.index(key)?;
+		self.record.get(i).filter(|cell| !self.columns.is_null(i, cell))
+	}
+
+	// list valued column split by its declared separator; null items become "" so
+	// that e.g. analyzed words and glosses stay aligned
+	pub fn get_list(&self, key: &str) -> Vec<String> {
+		let Some(i) = self.columns.index(key) else {
+			return vec![];
+		};
+		let Some(cell) = self.record.get(i).filter(|cell| !cell.is_empty()) else {
+			return vec![];
+		};
+
+		match self.columns.separators.get(&i) {
+			Some(separator) => cell
+				.split(separator.as_str())
+				.map(|item| match self.columns.is_null(i, item) {
+					true => String::new(),
+					false => item.to_string(),
+				})
+				.collect(),
+			None if self.columns.is_null(i, cell) => vec![],
+			None => vec![cell.to_string()],
+		}
+	}
+
+	pub fn get_or_empty(&self, key: &str) -> String {
+		self.get(key).unwrap_or_default().to_string()
+	}
+
+	pub fn require(&self, key: &str) -> Result<&'a str, String> {
+		self
+			.get(key)
+			.ok_or_else(|| format!("no value for column {key} in {:?}", self.record))
+	}
+}
+
+#[test]
+fn open_wals() {
+	match Metadata::find("cldf") {
+		Ok(m) => {
+			let values = m.table(Component::Values).map(|t| t.url.clone());
+			assert_eq!(values.as_deref(), Some("values.csv"));
+			let chapters = m.table(Component::Contributions).map(|t| t.url.clone());
+			assert_eq!(chapters.as_deref(), Some("chapters.csv"));
+		}
+
+		Err(e) => {
+			println!("{e}");
+		}
+	}
+}
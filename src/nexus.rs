@@ -0,0 +1,243 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use clap::Args;
+
+use crate::{
+	collect::collect_values_per_language,
+	dataset::Dataset,
+	distance::{print_dropped, Comparer, DistanceOptions},
+	filter::LanguageFilter,
+	util::ensure_dir,
+};
+
+const NEXUS_FILE: &str = "out/matrix.nex";
+const SYMBOLS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// Write the language by parameter matrix as NEXUS, with CHARACTERS and DISTANCES blocks, e.g. for SplitsTree
+#[derive(Debug, Args)]
+pub struct Nexus {
+	/// Languages to include, e.g. eng,ger,dut, besides those of --genus and --family; the
+	/// other filters (--macroarea, --sample, ...) narrow them down. All when none is given
+	#[arg(long, value_delimiter = ',')]
+	lang_ids: Vec<String>,
+
+	/// Only the parameters of these chapters, e.g. 81,82,83
+	#[arg(long, value_delimiter = ',')]
+	chapters: Vec<usize>,
+
+	#[command(flatten)]
+	options: DistanceOptions,
+
+	/// Leave out the languages with the most missing distances until none is missing,
+	/// which SplitsTree requires
+	#[arg(long)]
+	drop_incomplete: bool,
+}
+
+impl Nexus {
+	// the dataset is not filtered, the filter selects the languages with --lang-ids
+	pub fn write(self, dataset: &Dataset, filter: &LanguageFilter, out_dir: &Path) -> Result<(), String> {
+		let values = collect_values_per_language(dataset);
+
+		let lang_ids = self.lang_ids(dataset, filter)?;
+		let lang_ids = lang_ids
+			.into_iter()
+			.filter(|id| values.contains_key(id))
+			.collect::<Vec<_>>();
+		if lang_ids.is_empty() {
+			return Err("no languages with values selected".to_string());
+		}
+
+		let mut comparer = Comparer::new(dataset, &self.options)?;
+		comparer.restrict(self.parameters(dataset).into_iter().collect());
+		let mut matrix = comparer.matrix(&lang_ids)?;
+		if self.drop_incomplete {
+			print_dropped(&matrix.drop_incomplete());
+		}
+		let distances = matrix.nexus_distances_block()?;
+		let lang_ids = matrix.lang_ids;
+
+		let mut param_ids = self
+			.parameters(dataset)
+			.into_iter()
+			.filter(|id| lang_ids.iter().any(|l| values[l].contains_key(id)))
+			.collect::<Vec<_>>();
+		param_ids.sort_by(|a, b| parameter_order(a).cmp(&parameter_order(b)).then(a.cmp(b)));
+
+		let characters = characters_block(dataset, &lang_ids, &param_ids)?;
+
+		let path = out_dir.join(NEXUS_FILE);
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+
+		let text = format!("#NEXUS\n\n{}\n{characters}\n{distances}", nexus_taxa_block(&lang_ids));
+		fs::write(&path, text).map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!(
+			"{} languages, {} characters, written to {}",
+			lang_ids.len(),
+			param_ids.len(),
+			path.display()
+		);
+
+		Ok(())
+	}
+
+	// the parameters of the chosen chapters, all when none is chosen
+	fn parameters(&self, dataset: &Dataset) -> Vec<String> {
+		dataset
+			.parameters
+			.values()
+			.filter(|p| self.chapters.is_empty() || p.chapter_id.is_some_and(|c| self.chapters.contains(&c)))
+			.map(|p| p.id.clone())
+			.collect()
+	}
+
+	// the listed languages and those of --genus and --family, or all, then narrowed
+	// down by the rest of the filter
+	fn lang_ids(&self, dataset: &Dataset, filter: &LanguageFilter) -> Result<Vec<String>, String> {
+		if let Some(id) = self.lang_ids.iter().find(|id| dataset.language(id).is_none()) {
+			return Err(format!("language {id} not found"));
+		}
+
+		let mut ids = filter.group_members(dataset);
+		ids.extend(self.lang_ids.iter().cloned());
+		if ids.is_empty() {
+			ids.extend(dataset.languages.keys().cloned());
+		}
+		if let Some(narrowed) = filter.without_groups().selected(dataset)? {
+			ids.retain(|id| narrowed.contains(id));
+		}
+
+		let mut ids = ids.into_iter().collect::<Vec<_>>();
+		ids.sort();
+		Ok(ids)
+	}
+}
+
+// WALS style ids sort by their chapter number first, "2A" before "10A"
+pub fn parameter_order(id: &str) -> Option<usize> {
+	id.trim_end_matches(|c: char| !c.is_ascii_digit()).parse().ok()
+}
+
+// one multistate character per parameter; the states are the parameter's values in
+// numeric or else alphabetic order
+pub fn characters_block(dataset: &Dataset, lang_ids: &[String], param_ids: &[String]) -> Result<String, String> {
+	let values = collect_values_per_language(dataset);
+	let symbols = SYMBOLS.chars().collect::<Vec<_>>();
+
+	let mut states: HashMap<&String, Vec<String>> = HashMap::new();
+	for param_id in param_ids {
+		let mut vs = dataset
+			.value_languages(param_id)
+			.map(|vl| vl.keys().cloned().collect::<Vec<_>>())
+			.unwrap_or_default();
+		vs.sort_by(|a, b| match (a.parse::<f32>(), b.parse::<f32>()) {
+			(Ok(x), Ok(y)) => x.total_cmp(&y),
+			_ => a.cmp(b),
+		});
+
+		if vs.len() > symbols.len() {
+			return Err(format!("{param_id} has {} values, more than the {} nexus symbols", vs.len(), symbols.len()));
+		}
+		states.insert(param_id, vs);
+	}
+
+	let labels = param_ids
+		.iter()
+		.enumerate()
+		.map(|(i, param_id)| {
+			let name = dataset.parameters.get(param_id).map(|p| format!("{param_id} {}", p.name));
+			let state_labels = states[param_id]
+				.iter()
+				.map(|v| nexus_label(dataset.codes.name(param_id, v).unwrap_or(v)))
+				.intersperse(" ".to_string())
+				.collect::<String>();
+			format!("\t\t{} {} / {state_labels}", i + 1, nexus_label(&name.unwrap_or(param_id.clone())))
+		})
+		.intersperse(",\n".to_string())
+		.collect::<String>();
+
+	let rows = lang_ids
+		.iter()
+		.map(|lang_id| {
+			let row = param_ids
+				.iter()
+				.map(|param_id| {
+					values
+						.get(lang_id)
+						.and_then(|pv| pv.get(param_id))
+						.and_then(|v| states[param_id].iter().position(|s| s == v))
+						.map(|i| symbols[i])
+						.unwrap_or('?')
+				})
+				.collect::<String>();
+			format!("\t\t{} {row}\n", nexus_label(lang_id))
+		})
+		.collect::<String>();
+
+	let used = states.values().map(|s| s.len()).max().unwrap_or(1).max(1);
+	let symbols = symbols[..used].iter().map(|c| c.to_string()).intersperse(" ".to_string()).collect::<String>();
+
+	Ok(format!(
+		"BEGIN CHARACTERS;\n\tDIMENSIONS NCHAR={};\n\tFORMAT DATATYPE=STANDARD MISSING=? GAP=- SYMBOLS=\"{symbols}\";\n\tCHARSTATELABELS\n{labels}\n\t;\n\tMATRIX\n{rows}\t;\nEND;\n",
+		param_ids.len()
+	))
+}
+
+pub fn nexus_taxa_block(lang_ids: &[String]) -> String {
+	let labels = lang_ids
+		.iter()
+		.map(|id| format!("\t\t{}\n", nexus_label(id)))
+		.collect::<String>();
+
+	format!(
+		"BEGIN TAXA;\n\tDIMENSIONS NTAX={};\n\tTAXLABELS\n{labels}\t;\nEND;\n",
+		lang_ids.len()
+	)
+}
+
+// quoted unless it is a plain word
+pub fn nexus_label(s: &str) -> String {
+	match s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+		true => s.to_string(),
+		false => format!("'{}'", s.replace('\'', "''")),
+	}
+}
+
+#[test]
+fn characters_toy() {
+	let dataset = crate::dataset::toy();
+	let lang_ids = ["aab", "dda"].map(String::from);
+	let param_ids = ["P1", "P2", "P3"].map(String::from);
+
+	let block = characters_block(&dataset, &lang_ids, &param_ids).unwrap();
+	assert!(block.contains("NCHAR=3;"));
+	assert!(block.contains("SYMBOLS=\"0 1 2\""));
+	assert!(block.contains("\t\taab 00?\n\t\tdda 2?1\n"));
+	assert_eq!(nexus_label("Bai (Dali)"), "'Bai (Dali)'");
+
+	// the listed languages plus the genus, as the filter's --genus adds up here
+	let nexus = Nexus {
+		lang_ids: vec!["dda".to_string()],
+		chapters: vec![],
+		options: DistanceOptions {
+			metric: crate::distance::Metric::Hamming,
+			min_overlap: None,
+			weights: None,
+			ordinal: vec![],
+		},
+		drop_incomplete: false,
+	};
+	#[derive(clap::Parser)]
+	struct Cli {
+		#[command(flatten)]
+		filter: LanguageFilter,
+	}
+	let filter = |args: &[&str]| <Cli as clap::Parser>::parse_from([""].iter().chain(args)).filter;
+
+	assert_eq!(nexus.lang_ids(&dataset, &filter(&["--genus", "G3"])).unwrap(), ["cca", "ccb", "dda"]);
+	let filter = filter(&["--genus", "G3", "--macroarea", "Africa"]);
+	assert_eq!(nexus.lang_ids(&dataset, &filter).unwrap(), ["cca", "ccb"]);
+}
@@ -0,0 +1,122 @@
+use clap::Args;
+
+use crate::{
+	collect::collect_values_per_language,
+	dataset::Dataset,
+	geo::{coordinates, GeoIndex},
+};
+
+/// List the languages within --km of a point or of a language, nearest first, with their values
+#[derive(Debug, Args)]
+pub struct Near {
+	/// Centre on this language instead of --lat/--lon
+	#[arg(long, conflicts_with_all = ["lat", "lon"])]
+	lang: Option<String>,
+
+	#[arg(long, allow_hyphen_values = true, requires = "lon")]
+	lat: Option<f64>,
+
+	#[arg(long, allow_hyphen_values = true, requires = "lat")]
+	lon: Option<f64>,
+
+	#[arg(long, default_value_t = 500.0)]
+	km: f64,
+
+	/// Show the values of these parameters, e.g. 81A,82A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+}
+
+/// List the languages inside a latitude/longitude box; a west edge east of the east edge
+/// crosses the antimeridian
+#[derive(Debug, Args)]
+pub struct Bbox {
+	#[arg(long, allow_hyphen_values = true)]
+	south: f64,
+
+	#[arg(long, allow_hyphen_values = true)]
+	west: f64,
+
+	#[arg(long, allow_hyphen_values = true)]
+	north: f64,
+
+	#[arg(long, allow_hyphen_values = true)]
+	east: f64,
+
+	/// Show the values of these parameters, e.g. 81A,82A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+}
+
+impl Near {
+	pub fn print(self, dataset: &Dataset) -> Result<(), String> {
+		let center = match (&self.lang, self.lat, self.lon) {
+			(Some(id), _, _) => {
+				let lang = dataset.language(id).ok_or_else(|| format!("language {id} not found"))?;
+				coordinates(lang).ok_or_else(|| format!("language {id} has no coordinates"))?
+			}
+			(None, Some(lat), Some(lon)) => (lat, lon),
+			_ => return Err("give --lang or --lat and --lon".to_string()),
+		};
+
+		let index = GeoIndex::new(dataset.languages.values());
+		let found = index
+			.within_km(center, self.km)
+			.into_iter()
+			.map(|(id, km)| (id, Some(km)))
+			.collect::<Vec<_>>();
+
+		print_languages(dataset, &found, &self.params)
+	}
+}
+
+impl Bbox {
+	pub fn print(self, dataset: &Dataset) -> Result<(), String> {
+		if self.south > self.north {
+			return Err(format!("--south {} is north of --north {}", self.south, self.north));
+		}
+
+		let index = GeoIndex::new(dataset.languages.values());
+		let found = index
+			.in_bbox(self.south, self.west, self.north, self.east)
+			.into_iter()
+			.map(|id| (id, None))
+			.collect::<Vec<_>>();
+
+		print_languages(dataset, &found, &self.params)
+	}
+}
+
+// one line per language: id, name, genus, family, distance when given, then the values
+fn print_languages(dataset: &Dataset, found: &[(&str, Option<f64>)], param_ids: &[String]) -> Result<(), String> {
+	if let Some(p) = param_ids.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+		return Err(format!("parameter {p} not found"));
+	}
+
+	let values = collect_values_per_language(dataset);
+	for (lang_id, km) in found {
+		let Some(lang) = dataset.language(lang_id) else {
+			continue;
+		};
+
+		let mut line = format!(
+			"{:<8} {:<30} {:<20} {:<20}",
+			lang.id,
+			lang.name,
+			lang.genus.as_deref().unwrap_or_default(),
+			lang.family.as_deref().unwrap_or_default()
+		);
+		if let Some(km) = km {
+			line += &format!(" {km:>7.0} km");
+		}
+		for param_id in param_ids {
+			let value = values.get(*lang_id).and_then(|pv| pv.get(param_id));
+			let label = value.map(|v| dataset.codes.name(param_id, v).unwrap_or(v)).unwrap_or("-");
+			line += &format!(" | {param_id} {label}");
+		}
+		println!("{line}");
+	}
+	println!("{} languages", found.len());
+
+	Ok(())
+}
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+	dataset::Dataset,
+	stats::{weighted_frequencies, Weighting},
+	util::ensure_dir,
+};
+
+const RARITY_FILE: &str = "out/rarity.csv";
+
+/// Rank all languages by how unusual their values are (mean -ln frequency over coded features)
+#[derive(Debug, Args)]
+pub struct Rarity {
+	/// Let each genus or family count as one language in the frequencies
+	#[arg(long, value_enum, default_value_t = Weighting::None)]
+	weighting: Weighting,
+
+	/// Leave out languages coded for fewer features; with only a few datapoints one
+	/// rare value is enough to put a language at the top
+	#[arg(long, default_value_t = 30)]
+	min_coded: usize,
+
+	/// Number of languages printed after writing the table
+	#[arg(long, default_value_t = 10)]
+	top: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RarityRow {
+	rank: usize,
+	language_id: String,
+	language_name: String,
+	family: String,
+	genus: String,
+	macroarea: String,
+	coded: usize,
+	rarity: f32,
+}
+
+impl Rarity {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		let rows = self.rank(dataset);
+
+		let path = out_dir.join(RARITY_FILE);
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in rows.iter() {
+			w.serialize(row)
+				.map_err(|e| format!("{e} @{}/{}", path.display(), row.language_id))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		for row in rows.iter().take(self.top) {
+			println!(
+				"{:>4} {:<8} {:<30} {:>4} {:.4}",
+				row.rank, row.language_id, row.language_name, row.coded, row.rarity
+			);
+		}
+		println!("{} languages written to {}", rows.len(), path.display());
+
+		Ok(())
+	}
+
+	// most unusual first
+	fn rank(&self, dataset: &Dataset) -> Vec<RarityRow> {
+		let frequencies = weighted_frequencies(dataset, self.weighting);
+
+		let mut rows = dataset
+			.languages
+			.values()
+			.filter_map(|lang| {
+				let surprisals = dataset
+					.values_of_language(&lang.id)
+					.into_iter()
+					.filter_map(|v| frequencies.get(&v.parameter_id)?.get(v.value.as_ref()?))
+					.map(|f| -f.ln())
+					.collect::<Vec<_>>();
+
+				let coded = surprisals.len();
+				if coded == 0 || coded < self.min_coded {
+					return None;
+				}
+
+				Some(RarityRow {
+					rank: 0,
+					language_id: lang.id.clone(),
+					language_name: lang.name.clone(),
+					family: lang.family.clone().unwrap_or_default(),
+					genus: lang.genus.clone().unwrap_or_default(),
+					macroarea: lang.macroarea.clone().unwrap_or_default(),
+					coded,
+					rarity: surprisals.iter().sum::<f32>() / coded as f32,
+				})
+			})
+			.collect::<Vec<_>>();
+
+		rows.sort_by(|a, b| {
+			b.rarity
+				.total_cmp(&a.rarity)
+				.then_with(|| a.language_id.cmp(&b.language_id))
+		});
+		rows.iter_mut().enumerate().for_each(|(i, row)| row.rank = i + 1);
+
+		rows
+	}
+}
+
+#[test]
+fn rank_toy() {
+	let rarity = Rarity {
+		weighting: Weighting::None,
+		min_coded: 1,
+		top: 0,
+	};
+	let rows = rarity.rank(&crate::dataset::toy());
+
+	assert_eq!(rows.len(), 6);
+	assert_eq!(rows[0].language_id, "dda");
+	assert_eq!(rows[0].coded, 2);
+
+	let rarity = Rarity { min_coded: 3, ..rarity };
+	assert!(rarity.rank(&crate::dataset::toy()).iter().all(|r| r.coded == 3));
+
+	// zzz has a single datapoint, a value no other language has
+	let toy = crate::dataset::toy();
+	let mut languages = toy.languages.clone();
+	languages.insert(
+		"zzz".to_string(),
+		crate::csvs::Language {
+			id: "zzz".to_string(),
+			..toy.languages["dda"].clone()
+		},
+	);
+	let mut values = toy.values.clone();
+	values.push(crate::csvs::Value {
+		id: "P1-zzz".to_string(),
+		language_id: "zzz".to_string(),
+		value: Some("4".to_string()),
+		..values[0].clone()
+	});
+	let dataset = Dataset::new(
+		toy.metadata.clone(),
+		languages,
+		toy.parameters.clone(),
+		values,
+		toy.codes.clone(),
+		Default::default(),
+		Default::default(),
+	);
+
+	let rarity = Rarity { min_coded: 1, ..rarity };
+	assert_eq!(rarity.rank(&dataset)[0].language_id, "zzz");
+
+	#[derive(clap::Parser)]
+	struct Cli {
+		#[command(flatten)]
+		rarity: Rarity,
+	}
+	let rarity = <Cli as clap::Parser>::parse_from(["rarity"]).rarity;
+	assert_eq!(rarity.min_coded, 30);
+	let rarity = Rarity { min_coded: 2, ..rarity };
+	assert!(rarity.rank(&dataset).iter().all(|r| r.language_id != "zzz"));
+}
@@ -0,0 +1,292 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs,
+	path::Path,
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{
+	csvs::Language,
+	dataset::Dataset,
+	util::{ensure_dir, Rng},
+};
+
+const SAMPLE_FILE: &str = "out/sample.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SamplingMethod {
+	/// One language per genus
+	Genus,
+	/// Languages per family in proportion to a diversity score counting its subfamilies,
+	/// genera and languages, each level weighing less
+	Diversity,
+	/// Random genera per macroarea in proportion to the number of genera there
+	Stratified,
+}
+
+/// Draw a genealogically balanced sample; prints the ids and writes them to out/sample.txt,
+/// which can be passed back as --lang-file
+#[derive(Debug, Args)]
+pub struct Sampling {
+	#[arg(long, value_enum, default_value_t = SamplingMethod::Genus)]
+	method: SamplingMethod,
+
+	/// Number of languages to draw, an error when there are not enough; every genus with
+	/// --method genus when omitted
+	#[arg(long)]
+	size: Option<usize>,
+
+	/// Within a genus prefer the language coded for most of these parameters (all when omitted)
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	#[arg(long, default_value_t = 1)]
+	seed: u64,
+}
+
+impl Sampling {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let ids = self.draw(dataset)?;
+
+		let path = out_dir.join(SAMPLE_FILE);
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+		fs::write(&path, ids.join("\n") + "\n").map_err(|e| format!("{e} @{}", path.display()))?;
+
+		println!("{}", ids.join(" "));
+
+		let count = |key: fn(&Language) -> Option<&String>| {
+			ids.iter()
+				.filter_map(|id| dataset.language(id).and_then(key))
+				.collect::<HashSet<_>>()
+				.len()
+		};
+		let params = self.parameters(dataset);
+		let coded: usize = ids.iter().map(|id| self.coverage(dataset, &params, id)).sum();
+		eprintln!(
+			"{} languages, {} genera, {} families, {} macroareas, {:.1}% of {} parameters coded, written to {}",
+			ids.len(),
+			count(|l| l.genus.as_ref()),
+			count(|l| l.family.as_ref()),
+			count(|l| l.macroarea.as_ref()),
+			100.0 * coded as f32 / (ids.len() * params.len()).max(1) as f32,
+			params.len(),
+			path.display()
+		);
+
+		Ok(())
+	}
+
+	fn draw(&self, dataset: &Dataset) -> Result<Vec<String>, String> {
+		let mut rng = Rng::new(self.seed);
+		let params = self.parameters(dataset);
+		let size = || self.size.ok_or_else(|| "--size is needed for this method".to_string());
+
+		let mut ids = match self.method {
+			SamplingMethod::Genus => {
+				let mut genera = dataset.genera();
+				if let Some(size) = self.size {
+					if size > genera.len() {
+						return Err(format!("only {} genera for --size {size}", genera.len()));
+					}
+					rng.shuffle(&mut genera);
+					genera.truncate(size);
+				}
+
+				genera
+					.into_iter()
+					.filter_map(|g| self.spread(dataset, &params, dataset.languages_of_genus(g), 1, &mut rng).pop())
+					.collect::<Vec<_>>()
+			}
+
+			SamplingMethod::Diversity => {
+				let families = dataset.families();
+				let values = families.iter().map(|f| diversity_value(dataset, f)).collect::<Vec<_>>();
+
+				let strata = families.into_iter().map(|f| (f, dataset.languages_of_family(f)));
+				self.fill(dataset, &params, strata, apportion(&values, size()?), &mut rng)?
+			}
+
+			SamplingMethod::Stratified => {
+				let macroareas = dataset.macroareas();
+				let genera = macroareas
+					.iter()
+					.map(|m| {
+						dataset
+							.languages_of_macroarea(m)
+							.iter()
+							.filter_map(|id| dataset.language(id)?.genus.as_ref())
+							.collect::<HashSet<_>>()
+							.len() as f32
+					})
+					.collect::<Vec<_>>();
+
+				let strata = macroareas.into_iter().map(|m| (m, dataset.languages_of_macroarea(m)));
+				self.fill(dataset, &params, strata, apportion(&genera, size()?), &mut rng)?
+			}
+		};
+
+		ids.sort();
+		Ok(ids)
+	}
+
+	fn parameters(&self, dataset: &Dataset) -> Vec<String> {
+		match self.params.is_empty() {
+			true => dataset.parameters.keys().cloned().collect(),
+			false => self.params.clone(),
+		}
+	}
+
+	fn coverage(&self, dataset: &Dataset, params: &[String], lang_id: &str) -> usize {
+		let coded = dataset
+			.values_of_language(lang_id)
+			.into_iter()
+			.filter(|v| v.value.is_some())
+			.map(|v| v.parameter_id.as_str())
+			.collect::<HashSet<_>>();
+
+		params.iter().filter(|p| coded.contains(p.as_str())).count()
+	}
+
+	// each stratum's quota of languages, an error naming the strata with fewer
+	// languages than their quota
+	fn fill<'a>(
+		&self,
+		dataset: &Dataset,
+		params: &[String],
+		strata: impl Iterator<Item = (&'a String, &'a [String])>,
+		quotas: Vec<usize>,
+		rng: &mut Rng,
+	) -> Result<Vec<String>, String> {
+		let (mut ids, mut short) = (vec![], vec![]);
+		for ((name, lang_ids), k) in strata.zip(quotas) {
+			let picked = self.spread(dataset, params, lang_ids, k, rng);
+			if picked.len() < k {
+				short.push(format!("{name} ({} of {k})", picked.len()));
+			}
+			ids.extend(picked);
+		}
+
+		match short.is_empty() {
+			true => Ok(ids),
+			false => Err(format!(
+				"only {} languages drawn, too few in {}; lower --size",
+				ids.len(),
+				short.join(", ")
+			)),
+		}
+	}
+
+	// k languages taken in turns from the genera of the candidates, in random genus
+	// order, each genus giving its best covered language first
+	fn spread(&self, dataset: &Dataset, params: &[String], lang_ids: &[String], k: usize, rng: &mut Rng) -> Vec<String> {
+		let mut genera: HashMap<&str, Vec<(usize, &String)>> = HashMap::new();
+		for id in lang_ids {
+			let genus = dataset.language(id).and_then(|l| l.genus.as_deref()).unwrap_or(id);
+			genera.entry(genus).or_default().push((self.coverage(dataset, params, id), id));
+		}
+
+		let mut genera = genera.into_iter().collect::<Vec<_>>();
+		genera.sort_by(|a, b| a.0.cmp(b.0));
+		rng.shuffle(&mut genera);
+		for (_, langs) in genera.iter_mut() {
+			langs.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(a.1)));
+		}
+
+		let mut picked = vec![];
+		while picked.len() < k && genera.iter().any(|(_, langs)| !langs.is_empty()) {
+			for (_, langs) in genera.iter_mut() {
+				if picked.len() < k
+					&& let Some((_, id)) = langs.pop()
+				{
+					picked.push(id.clone());
+				}
+			}
+		}
+
+		picked
+	}
+}
+
+// subfamilies + genera / 2 + languages / 3, a rough score of our own rather than
+// Rijkhoff & Bakker's diversity value: internal structure weighs more than the sheer
+// number of languages, but the depth of the tree is not looked at
+fn diversity_value(dataset: &Dataset, family: &str) -> f32 {
+	let langs = dataset
+		.languages_of_family(family)
+		.iter()
+		.filter_map(|id| dataset.language(id))
+		.collect::<Vec<_>>();
+
+	let subfamilies = langs
+		.iter()
+		.map(|l| l.subfamily.as_ref().or(l.genus.as_ref()).unwrap_or(&l.id))
+		.collect::<HashSet<_>>()
+		.len();
+	let genera = langs
+		.iter()
+		.map(|l| l.genus.as_ref().unwrap_or(&l.id))
+		.collect::<HashSet<_>>()
+		.len();
+
+	subfamilies as f32 + genera as f32 / 2.0 + langs.len() as f32 / 3.0
+}
+
+// total split in proportion to the weights by largest remainders
+fn apportion(weights: &[f32], total: usize) -> Vec<usize> {
+	let sum: f32 = weights.iter().sum();
+	if sum <= 0.0 {
+		return vec![0; weights.len()];
+	}
+
+	let quotas = weights.iter().map(|w| w / sum * total as f32).collect::<Vec<_>>();
+	let mut counts = quotas.iter().map(|q| q.floor() as usize).collect::<Vec<_>>();
+
+	let mut by_remainder = (0..weights.len()).collect::<Vec<_>>();
+	by_remainder.sort_by(|a, b| (quotas[*b] - quotas[*b].floor()).total_cmp(&(quotas[*a] - quotas[*a].floor())));
+	let missing = total.saturating_sub(counts.iter().sum());
+	for i in by_remainder.into_iter().take(missing) {
+		counts[i] += 1;
+	}
+
+	counts
+}
+
+#[test]
+fn draw_toy() {
+	let dataset = crate::dataset::toy();
+	let mut sampling = Sampling {
+		method: SamplingMethod::Genus,
+		size: None,
+		params: vec![],
+		seed: 1,
+	};
+	assert_eq!(sampling.draw(&dataset).unwrap(), ["aaa", "bba", "cca", "dda"]);
+
+	sampling.method = SamplingMethod::Diversity;
+	sampling.size = Some(3);
+	let ids = sampling.draw(&dataset).unwrap();
+	let families = ids
+		.iter()
+		.filter_map(|id| dataset.language(id)?.family.clone())
+		.collect::<HashSet<_>>();
+	assert_eq!(families.len(), 3);
+
+	sampling.method = SamplingMethod::Stratified;
+	assert_eq!(sampling.draw(&dataset).unwrap().len(), 3);
+
+	// South America has one language for its quota of 2
+	sampling.size = Some(7);
+	let error = "only 6 languages drawn, too few in South America (1 of 2); lower --size";
+	assert_eq!(sampling.draw(&dataset), Err(error.to_string()));
+	sampling.method = SamplingMethod::Genus;
+	assert!(sampling.draw(&dataset).is_err());
+	assert_eq!(apportion(&[4.0, 2.0, 2.0], 3), [1, 1, 1]);
+	assert_eq!(apportion(&[4.0, 2.0, 2.0], 4), [2, 1, 1]);
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+use crate::dataset::Dataset;
+
+// how much a single language counts towards the frequency of its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Weighting {
+	None,
+	Genus,
+	Family,
+}
+
+impl Weighting {
+	// the group a language shares its vote with; languages without one vote alone
+	pub fn group<'a>(&self, dataset: &'a Dataset, lang_id: &'a str) -> &'a str {
+		let lang = dataset.language(lang_id);
+		let group = match self {
+			Self::None => None,
+			Self::Genus => lang.and_then(|l| l.genus.as_deref()),
+			Self::Family => lang.and_then(|l| l.family.as_deref()),
+		};
+
+		group.unwrap_or(lang_id)
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParameterStats {
+	pub coded: usize, // languages with any value for the parameter
+	pub value_counts: HashMap<String, usize>,
+}
+
+impl ParameterStats {
+	pub fn count(&self, value: &str) -> usize {
+		self.value_counts.get(value).copied().unwrap_or_default()
+	}
+
+	// share of the coded languages that have the value
+	pub fn frequency(&self, value: &str) -> f32 {
+		match self.coded {
+			0 => 0.0,
+			coded => self.count(value) as f32 / coded as f32,
+		}
+	}
+}
+
+// value counts of every parameter, computed once per dataset
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+	pub parameters: HashMap<String, ParameterStats>,
+	pub datapoints: usize, // coded (language, parameter) pairs over all parameters
+}
+
+impl Statistics {
+	pub fn new(dataset: &Dataset) -> Self {
+		let parameters: HashMap<String, ParameterStats> = dataset
+			.parameters
+			.keys()
+			.filter_map(|param_id| {
+				let value_langs = dataset.value_languages(param_id)?;
+				let value_counts: HashMap<String, usize> = value_langs
+					.iter()
+					.map(|(value, langs)| (value.clone(), langs.len()))
+					.collect();
+				// a language with several values is still one coded language
+				let coded = value_langs.values().flatten().collect::<HashSet<_>>().len();
+
+				Some((param_id.clone(), ParameterStats { coded, value_counts }))
+			})
+			.collect();
+
+		let datapoints = parameters.values().map(|p| p.coded).sum();
+
+		Self {
+			parameters,
+			datapoints,
+		}
+	}
+
+	pub fn parameter(&self, param_id: &str) -> Option<&ParameterStats> {
+		self.parameters.get(param_id)
+	}
+
+	pub fn frequency(&self, param_id: &str, value: &str) -> Option<f32> {
+		self.parameter(param_id).map(|p| p.frequency(value))
+	}
+
+	// share of all datapoints of the dataset, regardless of parameter
+	pub fn global_ratio(&self, param_id: &str, value: &str) -> Option<f32> {
+		let count = self.parameter(param_id)?.count(value);
+		match self.datapoints {
+			0 => None,
+			total => Some(count as f32 / total as f32),
+		}
+	}
+}
+
+type ValueFrequencies = HashMap<String, f32>; // <value, frequency>
+
+// frequencies of every value where each genus or family coded for a parameter
+// splits one vote evenly among its languages
+pub fn weighted_frequencies(dataset: &Dataset, weighting: Weighting) -> HashMap<String, ValueFrequencies> {
+	dataset
+		.parameters
+		.keys()
+		.filter_map(|param_id| {
+			let value_langs = dataset.value_languages(param_id)?;
+
+			let mut group_sizes: HashMap<&str, usize> = HashMap::new();
+			for lang_id in value_langs.values().flatten().collect::<HashSet<_>>() {
+				*group_sizes.entry(weighting.group(dataset, lang_id)).or_default() += 1;
+			}
+			let groups = group_sizes.len() as f32;
+
+			let frequencies = value_langs
+				.iter()
+				.map(|(value, langs)| {
+					let votes: f32 = langs
+						.iter()
+						.map(|l| 1.0 / group_sizes[weighting.group(dataset, l)] as f32)
+						.sum();
+					(value.clone(), votes / groups)
+				})
+				.collect();
+
+			Some((param_id.clone(), frequencies))
+		})
+		.collect()
+}
+
+#[test]
+fn per_parameter() {
+	let stats = Statistics::new(&crate::dataset::toy());
+
+	assert_eq!(stats.datapoints, 15);
+	assert_eq!(stats.parameter("P1").map(|p| p.coded), Some(6));
+	assert_eq!(stats.frequency("P1", "1"), Some(0.5));
+	assert_eq!(stats.frequency("P2", "2"), Some(0.6));
+	assert_eq!(stats.global_ratio("P1", "1"), Some(0.2));
+
+	// aaa coded with a second value for P1
+	let toy = crate::dataset::toy();
+	let mut values = toy.values.clone();
+	values.push(crate::csvs::Value {
+		id: "P1-aaa-2".to_string(),
+		value: Some("2".to_string()),
+		..values[0].clone()
+	});
+	let dataset = Dataset::new(
+		toy.metadata.clone(),
+		toy.languages.clone(),
+		toy.parameters.clone(),
+		values,
+		toy.codes.clone(),
+		HashMap::new(),
+		HashMap::new(),
+	);
+	let stats = Statistics::new(&dataset);
+	assert_eq!(stats.datapoints, 15);
+	assert_eq!(stats.parameter("P1").map(|p| p.coded), Some(6));
+	assert_eq!(stats.frequency("P1", "2"), Some(0.5));
+}
+
+#[test]
+fn weighted() {
+	let dataset = crate::dataset::toy();
+
+	let unweighted = weighted_frequencies(&dataset, Weighting::None);
+	assert_eq!(unweighted["P1"]["1"], 0.5);
+
+	let genus = weighted_frequencies(&dataset, Weighting::Genus);
+	assert_eq!(genus["P1"]["1"], 0.5);
+	assert_eq!(genus["P1"]["2"], 0.25);
+
+	let family = weighted_frequencies(&dataset, Weighting::Family);
+	assert!((family["P1"]["1"] - 1.0 / 3.0).abs() < 1e-6);
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, path::Path};
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+	collect::{collect_values_per_language, collect_values_per_param},
+	dataset::Dataset,
+	stats::{Statistics, Weighting},
+	util::ensure_dir,
+};
+
+const UNIVERSALS_FILE: &str = "out/universals.csv";
+
+/// Search implicational tendencies "if P1=a then P2=b" over all pairs of parameters
+#[derive(Debug, Args)]
+pub struct Universals {
+	/// Least number of languages with P1=a that are also coded for P2
+	#[arg(long, default_value_t = 10)]
+	min_support: usize,
+
+	/// Least share of those languages having P2=b
+	#[arg(long, default_value_t = 0.9)]
+	min_confidence: f32,
+
+	/// Least number of genera among those languages
+	#[arg(long, default_value_t = 1)]
+	min_genera: usize,
+
+	/// Only look at implications between these parameters, e.g. 81A,85A,86A
+	#[arg(long, value_delimiter = ',')]
+	params: Vec<String>,
+
+	/// Number of implications printed
+	#[arg(long, default_value_t = 20)]
+	top: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Implication {
+	antecedent_parameter: String,
+	antecedent_value: String,
+	consequent_parameter: String,
+	consequent_value: String,
+	support: usize, // languages with P1=a coded for P2
+	agreeing: usize, // of which P2=b
+	confidence: f32, // agreeing / support
+	baseline: f32, // share of P2=b among all languages coded for P2
+	genera: usize, // genera among the supporting languages
+	genus_confidence: f32, // confidence with each genus casting one vote
+	counterexamples: String,
+}
+
+impl Universals {
+	pub fn write(self, dataset: &Dataset, out_dir: &Path) -> Result<(), String> {
+		if let Some(p) = self.params.iter().find(|p| !dataset.parameters.contains_key(*p)) {
+			return Err(format!("parameter {p} not found"));
+		}
+
+		let implications = self.mine(dataset);
+
+		let path = out_dir.join(UNIVERSALS_FILE);
+		if let Some(dir) = path.parent() {
+			ensure_dir(dir)?;
+		}
+
+		let mut w = csv::Writer::from_path(&path).map_err(|e| format!("{e} @{}", path.display()))?;
+		for row in implications.iter() {
+			w.serialize(row).map_err(|e| format!("{e} @{}", path.display()))?;
+		}
+		w.flush().map_err(|e| format!("{e} @{}", path.display()))?;
+
+		for i in implications.iter().take(self.top) {
+			println!(
+				"{}={} => {}={}  {}/{} ({:.3}, baseline {:.3}), {} genera ({:.3})",
+				i.antecedent_parameter,
+				dataset.codes.label(&i.antecedent_parameter, &i.antecedent_value),
+				i.consequent_parameter,
+				dataset.codes.label(&i.consequent_parameter, &i.consequent_value),
+				i.agreeing,
+				i.support,
+				i.confidence,
+				i.baseline,
+				i.genera,
+				i.genus_confidence
+			);
+		}
+		println!("{} implications written to {}", implications.len(), path.display());
+
+		Ok(())
+	}
+
+	// most confident first
+	fn mine(&self, dataset: &Dataset) -> Vec<Implication> {
+		let per_param = collect_values_per_param(dataset);
+		let per_lang = collect_values_per_language(dataset);
+		let stats = Statistics::new(dataset);
+
+		let mut param_ids = per_param
+			.keys()
+			.filter(|p| self.params.is_empty() || self.params.contains(p))
+			.collect::<Vec<_>>();
+		param_ids.sort();
+
+		let mut implications = vec![];
+		for p1 in param_ids.iter() {
+			for (a, langs) in per_param[*p1].iter() {
+				for p2 in param_ids.iter().filter(|p2| *p2 != p1) {
+					// <b, language_ids with P1=a and P2=b>
+					let mut consequents: HashMap<&String, Vec<&String>> = HashMap::new();
+					for lang_id in langs {
+						if let Some(b) = per_lang.get(lang_id).and_then(|pv| pv.get(*p2)) {
+							consequents.entry(b).or_default().push(lang_id);
+						}
+					}
+
+					let support = consequents.values().map(|ls| ls.len()).sum::<usize>();
+					if support == 0 || support < self.min_support {
+						continue;
+					}
+
+					// supporting languages per genus
+					let mut genus_sizes: HashMap<&str, usize> = HashMap::new();
+					for lang_id in consequents.values().flatten() {
+						*genus_sizes.entry(Weighting::Genus.group(dataset, lang_id)).or_default() += 1;
+					}
+					if genus_sizes.len() < self.min_genera {
+						continue;
+					}
+
+					for (b, agreeing) in consequents.iter() {
+						let confidence = agreeing.len() as f32 / support as f32;
+						if confidence < self.min_confidence {
+							continue;
+						}
+
+						let votes: f32 = agreeing
+							.iter()
+							.map(|l| 1.0 / genus_sizes[Weighting::Genus.group(dataset, l)] as f32)
+							.sum();
+
+						let mut counterexamples = consequents
+							.iter()
+							.filter(|(other, _)| other != &b)
+							.flat_map(|(_, ls)| ls.iter().map(|l| l.as_str()))
+							.collect::<Vec<_>>();
+						counterexamples.sort();
+
+						implications.push(Implication {
+							antecedent_parameter: (*p1).clone(),
+							antecedent_value: a.clone(),
+							consequent_parameter: (*p2).clone(),
+							consequent_value: (*b).clone(),
+							support,
+							agreeing: agreeing.len(),
+							confidence,
+							baseline: stats.frequency(p2, b).unwrap_or_default(),
+							genera: genus_sizes.len(),
+							genus_confidence: votes / genus_sizes.len() as f32,
+							counterexamples: counterexamples.join(" "),
+						});
+					}
+				}
+			}
+		}
+
+		implications.sort_by(|x, y| {
+			y.confidence
+				.total_cmp(&x.confidence)
+				.then(y.support.cmp(&x.support))
+				.then(x.antecedent_parameter.cmp(&y.antecedent_parameter))
+				.then(x.antecedent_value.cmp(&y.antecedent_value))
+				.then(x.consequent_parameter.cmp(&y.consequent_parameter))
+				.then(x.consequent_value.cmp(&y.consequent_value))
+		});
+
+		implications
+	}
+}
+
+#[test]
+fn mine_toy() {
+	let universals = Universals {
+		min_support: 2,
+		min_confidence: 0.6,
+		min_genera: 2,
+		params: vec![],
+		top: 0,
+	};
+	let implications = universals.mine(&crate::dataset::toy());
+	let find = |p1: &str, a: &str, p2: &str| {
+		implications
+			.iter()
+			.find(|i| i.antecedent_parameter == p1 && i.antecedent_value == a && i.consequent_parameter == p2)
+	};
+
+	let p1_p3 = find("P1", "1", "P3").unwrap();
+	assert_eq!((p1_p3.support, p1_p3.confidence, p1_p3.genera), (2, 1.0, 2));
+
+	let p1_p2 = find("P1", "1", "P2").unwrap();
+	assert_eq!(p1_p2.consequent_value, "1");
+	assert_eq!(p1_p2.counterexamples, "bba");
+	assert_eq!(p1_p2.genus_confidence, 0.5);
+}
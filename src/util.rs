@@ -1,4 +1,9 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+	collections::HashMap,
+	fs,
+	hash::Hash,
+	path::{Path, PathBuf},
+};
 
 pub fn flatten<T, E>(result: Result<Result<T, E>, E>) -> Result<T, E> {
 	match result {
@@ -7,6 +12,12 @@ pub fn flatten<T, E>(result: Result<Result<T, E>, E>) -> Result<T, E> {
 	}
 }
 
+pub fn ensure_dir<P: AsRef<Path>>(path: P) -> Result<PathBuf, String> {
+	let path = path.as_ref();
+	fs::create_dir_all(path).map_err(|e| format!("{e} @create_dir_all {}", path.display()))?;
+	Ok(path.to_path_buf())
+}
+
 pub fn all_ok<T, E, I>(results: I) -> Result<Vec<T>, E>
 where
 	I: Iterator<Item = Result<T, E>>,
@@ -96,6 +107,35 @@ where
 		.collect()
 }
 
+// splitmix64; reproducible draws from a seed without pulling in a crate
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Self(seed)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		z ^ (z >> 31)
+	}
+
+	// uniform in 0..n
+	pub fn below(&mut self, n: usize) -> usize {
+		(self.next_u64() % n.max(1) as u64) as usize
+	}
+
+	pub fn shuffle<T>(&mut self, v: &mut [T]) {
+		for i in (1..v.len()).rev() {
+			v.swap(i, self.below(i + 1));
+		}
+	}
+}
+
 #[test]
 fn transform() {
 	let h: HashMap<&str, HashMap<&str, usize>> = [